@@ -1,6 +1,7 @@
-use core::ffi::c_void;
+use core::mem::size_of;
 
-use alloc::vec::Vec;
+use alloc::vec;
+use uefi::{file::File, ResultExt};
 
 type Elf64Addr = u64;
 type Elf64Half = u16;
@@ -64,8 +65,12 @@ struct Elf64Phdr {
     pub p_align: Elf64XWord,
 }
 
-pub fn load_executable(file: &Vec<u8>) -> Result<usize, uefi::Error> {
-    let hdr = unsafe { &*(file.as_ptr() as *const Elf64Ehdr) };
+pub fn load_executable(file: &mut File) -> Result<usize, uefi::Error> {
+    let mut hdr_buffer = [0; size_of::<Elf64Ehdr>()];
+    file.set_position(0)?;
+    file.read_exact(&mut hdr_buffer, None)
+        .context("Failed to read ELF header")?;
+    let hdr: Elf64Ehdr = unsafe { core::ptr::read_unaligned(hdr_buffer.as_ptr() as *const _) };
 
     // Check ELF MAG
     if hdr.e_ident[EI_MAG0] != ELFMAG0
@@ -113,20 +118,49 @@ pub fn load_executable(file: &Vec<u8>) -> Result<usize, uefi::Error> {
         ));
     }
 
-    // Load the execuatable
-    let mut phdr_ptr = unsafe { file.as_ptr().offset(hdr.e_phoff as isize) };
-    let mut phdr = unsafe { &*(phdr_ptr as *const Elf64Phdr) };
+    // Read the program headers
+    if (hdr.e_phentsize as usize) < size_of::<Elf64Phdr>() {
+        return Err(uefi::Error::new(
+            uefi::Status::UNSUPPORTED,
+            "Invalid program header size",
+        ));
+    }
+
+    let mut phdrs = vec![0; hdr.e_phnum as usize * hdr.e_phentsize as usize];
+    file.set_position(hdr.e_phoff)?;
+    file.read_exact(&mut phdrs, None)
+        .context("Failed to read program headers")?;
+
+    // Load the execuatable straight into its pages
     let mut i = 0;
     while i < hdr.e_phnum {
+        let phdr: Elf64Phdr = unsafe {
+            core::ptr::read_unaligned(
+                phdrs.as_ptr().add(i as usize * hdr.e_phentsize as usize) as *const _
+            )
+        };
+
         if phdr.p_type == PT_LOAD {
-            uefi::memory::allocate_pages(phdr.p_memsz as usize, phdr.p_paddr)?;
+            // Only p_memsz bytes are allocated
+            if phdr.p_filesz > phdr.p_memsz {
+                return Err(uefi::error!(
+                    uefi::Status::LOAD_ERROR,
+                    "Segment {} is larger in the file than in memory",
+                    i
+                ));
+            }
+
+            uefi::memory::allocate_pages(phdr.p_memsz as usize, phdr.p_paddr).with_context(
+                format_args!("Failed to load segment {} at {:#X}", i, phdr.p_paddr),
+            )?;
 
             if phdr.p_filesz > 0 {
-                uefi::memory::copy_mem(
-                    phdr.p_paddr as *mut c_void,
-                    unsafe { file.as_ptr().offset(phdr.p_offset as isize) as *const c_void },
-                    phdr.p_filesz as usize,
-                );
+                // The pages were just allocated for this segment
+                let segment = unsafe {
+                    core::slice::from_raw_parts_mut(phdr.p_paddr as *mut u8, phdr.p_filesz as usize)
+                };
+                file.read_at(phdr.p_offset, segment, None)
+                    .with_context(format_args!("Failed to read segment {}", i))?;
             }
 
             let diff = phdr.p_memsz - phdr.p_filesz;
@@ -140,8 +174,6 @@ pub fn load_executable(file: &Vec<u8>) -> Result<usize, uefi::Error> {
         }
 
         i += 1;
-        phdr_ptr = unsafe { phdr_ptr.offset(hdr.e_phentsize as isize) };
-        phdr = unsafe { &*(phdr_ptr as *const Elf64Phdr) };
     }
 
     Ok(hdr.e_entry as usize)
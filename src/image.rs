@@ -0,0 +1,202 @@
+use alloc::{vec, vec::Vec};
+use uefi::graphics::BltPixel;
+
+const MAX_DIMENSION: usize = 16384;
+
+const BMP_MAGIC: &[u8] = b"BM";
+const BMP_PIXEL_OFFSET: usize = 10;
+const BMP_WIDTH: usize = 18;
+const BMP_HEIGHT: usize = 22;
+const BMP_BPP: usize = 28;
+const BMP_COMPRESSION: usize = 30;
+const BMP_HEADER_SIZE: usize = 34;
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+
+const QOI_MAGIC: &[u8] = b"qoif";
+const QOI_HEADER_SIZE: usize = 14;
+
+const QOI_OP_INDEX: u8 = 0x00;
+const QOI_OP_DIFF: u8 = 0x40;
+const QOI_OP_LUMA: u8 = 0x80;
+const QOI_OP_RUN: u8 = 0xC0;
+const QOI_OP_RGB: u8 = 0xFE;
+const QOI_OP_RGBA: u8 = 0xFF;
+const QOI_MASK: u8 = 0xC0;
+
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<BltPixel>,
+}
+
+pub fn decode(data: &[u8]) -> Result<Image, uefi::Error> {
+    if data.starts_with(BMP_MAGIC) {
+        decode_bmp(data)
+    } else if data.starts_with(QOI_MAGIC) {
+        decode_qoi(data)
+    } else {
+        Err(uefi::Error::new(
+            uefi::Status::UNSUPPORTED,
+            "Unknown image format",
+        ))
+    }
+}
+
+fn decode_bmp(data: &[u8]) -> Result<Image, uefi::Error> {
+    if data.len() < BMP_HEADER_SIZE {
+        return Err(truncated());
+    }
+
+    let pixel_offset = read_u32_le(data, BMP_PIXEL_OFFSET) as usize;
+    let width = read_u32_le(data, BMP_WIDTH) as i32;
+    let height = read_u32_le(data, BMP_HEIGHT) as i32;
+    let bpp = u16::from_le_bytes([data[BMP_BPP], data[BMP_BPP + 1]]) as usize;
+    let compression = read_u32_le(data, BMP_COMPRESSION);
+
+    // Only uncompressed 24 and 32 bit images are supported
+    if !(bpp == 24 && compression == BI_RGB
+        || bpp == 32 && (compression == BI_RGB || compression == BI_BITFIELDS))
+    {
+        return Err(uefi::Error::new(
+            uefi::Status::UNSUPPORTED,
+            "Unsupported BMP format",
+        ));
+    }
+
+    // A negative height means the rows are stored top-down
+    let top_down = height < 0;
+    let width = width.unsigned_abs() as usize;
+    let height = height.unsigned_abs() as usize;
+    check_dimensions(width, height)?;
+
+    let stride = (bpp * width).div_ceil(32) * 4;
+    if pixel_offset + stride * height > data.len() {
+        return Err(truncated());
+    }
+
+    let bytes_per_pixel = bpp / 8;
+    let mut pixels = vec![BltPixel::default(); width * height];
+    for row in 0..height {
+        let src_row = if top_down { row } else { height - row - 1 };
+        let src = &data[pixel_offset + src_row * stride..];
+        for col in 0..width {
+            let px = &src[col * bytes_per_pixel..];
+            pixels[row * width + col] = BltPixel {
+                blue: px[0],
+                green: px[1],
+                red: px[2],
+                reserved: 0,
+            };
+        }
+    }
+
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+fn decode_qoi(data: &[u8]) -> Result<Image, uefi::Error> {
+    if data.len() < QOI_HEADER_SIZE {
+        return Err(truncated());
+    }
+
+    let width = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+    let height = u32::from_be_bytes([data[8], data[9], data[10], data[11]]) as usize;
+    check_dimensions(width, height)?;
+
+    let mut pixels = Vec::with_capacity(width * height);
+    let mut index = [[0u8; 4]; 64];
+    let mut px = [0u8, 0, 0, 255];
+    let mut run = 0;
+    let mut i = QOI_HEADER_SIZE;
+
+    while pixels.len() < width * height {
+        if run > 0 {
+            run -= 1;
+        } else {
+            let byte = *data.get(i).ok_or_else(truncated)?;
+            i += 1;
+
+            if byte == QOI_OP_RGB || byte == QOI_OP_RGBA {
+                let len = if byte == QOI_OP_RGB { 3 } else { 4 };
+                let chunk = data.get(i..i + len).ok_or_else(truncated)?;
+                px[..len].copy_from_slice(chunk);
+                i += len;
+            } else {
+                match byte & QOI_MASK {
+                    QOI_OP_INDEX => px = index[byte as usize],
+                    QOI_OP_DIFF => {
+                        px[0] = px[0].wrapping_add((byte >> 4) & 0x03).wrapping_sub(2);
+                        px[1] = px[1].wrapping_add((byte >> 2) & 0x03).wrapping_sub(2);
+                        px[2] = px[2].wrapping_add(byte & 0x03).wrapping_sub(2);
+                    }
+                    QOI_OP_LUMA => {
+                        let next = *data.get(i).ok_or_else(truncated)?;
+                        i += 1;
+
+                        let dg = (byte & 0x3F).wrapping_sub(32);
+                        px[0] = px[0]
+                            .wrapping_add(dg)
+                            .wrapping_sub(8)
+                            .wrapping_add((next >> 4) & 0x0F);
+                        px[1] = px[1].wrapping_add(dg);
+                        px[2] = px[2]
+                            .wrapping_add(dg)
+                            .wrapping_sub(8)
+                            .wrapping_add(next & 0x0F);
+                    }
+                    QOI_OP_RUN => run = byte & 0x3F,
+                    _ => unreachable!(),
+                }
+            }
+
+            let hash = (px[0] as usize * 3
+                + px[1] as usize * 5
+                + px[2] as usize * 7
+                + px[3] as usize * 11)
+                % 64;
+            index[hash] = px;
+        }
+
+        pixels.push(BltPixel {
+            blue: px[2],
+            green: px[1],
+            red: px[0],
+            reserved: 0,
+        });
+    }
+
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+fn check_dimensions(width: usize, height: usize) -> Result<(), uefi::Error> {
+    if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+        Err(uefi::Error::new(
+            uefi::Status::UNSUPPORTED,
+            "Invalid image dimensions",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn read_u32_le(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+fn truncated() -> uefi::Error {
+    uefi::Error::new(uefi::Status::END_OF_FILE, "Image data is truncated")
+}
@@ -1,17 +1,56 @@
 #![no_std]
 #![no_main]
 
+use alloc::{string::String, vec::Vec};
 use core::ffi::c_void;
-use uefi::{exit_boot_services, print, println};
+use uefi::{eprintln, exit_boot_services, print, println, ResultExt};
 
 extern crate alloc;
 
 mod elf;
+mod image;
+mod splash;
+
+const BOOT_STAGES: usize = 8;
+
+const DEFAULT_KERNEL_PATH: &str = "kernel.elf";
+
+const BOOT_LOG_DIRECTORY: &str = "\\los";
+const BOOT_LOG_PATH: &str = "\\los\\bootlog.txt";
+
+// Load options the bootloader handles itself
+const BOOTLOADER_OPTIONS: &[&str] = &[
+    "kernel=",
+    "root=",
+    "chainload=",
+    "display=",
+    "dtb=",
+    "initrd=",
+];
+
+static mut PANICKING: bool = false;
+
+struct BootOptions<'a> {
+    kernel_path: &'a str,
+    root_partition: Option<uefi::config_table::GUID>,
+    // Overrides the firmware's device tree, if it has one
+    device_tree_path: Option<&'a str>,
+    // Only described to the kernel through the device tree
+    initrd_path: Option<&'a str>,
+    // The load options without the bootloader's own
+    command_line: String,
+}
 
 type KernelEntry = extern "efiapi" fn(
-    graphics_info: *const uefi::graphics::GraphicsMode,
+    graphics_info: *const uefi::graphics::GraphicsOutputs,
     memory_map: *const uefi::memory::MemoryMap,
-    rdsp: *const c_void,
+    memory_attributes: *const uefi::memory::MemoryAttributes,
+    rsdp: *const uefi::config_table::Rsdp,
+    boot_disk: *const uefi::block::BootDisk,
+    acpi_info: *const uefi::acpi::AcpiInfo,
+    smbios: *const uefi::smbios::EntryPoint,
+    config_tables: *const uefi::config_table::ConfigTables,
+    device_tree: *const c_void,
 );
 
 #[no_mangle]
@@ -19,47 +58,449 @@ extern "efiapi" fn efi_main(image_handle: *const c_void, system_table: *const c_
     match uefi::initialize(system_table, image_handle, main) {
         Ok(()) => 0,
         Err(err) => {
-            println!("\r\nFATAL ERROR: {}", err);
+            let stderr = uefi::console::standard_error();
+            if let Some(stderr) = stderr {
+                stderr
+                    .set_attribute(uefi::console::Color::LightRed, uefi::console::Color::Black)
+                    .ok();
+            }
+
+            eprintln!("\nFATAL ERROR: {}", err);
+            save_boot_log();
+
+            if let Some(stderr) = stderr {
+                stderr
+                    .set_attribute(uefi::console::Color::LightGray, uefi::console::Color::Black)
+                    .ok();
+            }
+
             err.into()
         }
     }
 }
 
 fn main() -> Result<(), uefi::Error> {
+    let all_options = uefi::get_load_options().unwrap_or_default();
+
+    // Anything after "--" is passed on to a chainloaded image
+    let (options, chain_options) = match all_options.split_once(" -- ") {
+        Some((options, chain_options)) => (options, chain_options.trim()),
+        None => (all_options.as_str(), ""),
+    };
+    let verbose = options.split_whitespace().any(|option| option == "verbose");
+
+    // If the chainloaded image exits, boot the kernel instead
+    if let Some(path) = options
+        .split_whitespace()
+        .find_map(|option| option.strip_prefix("chainload="))
+    {
+        if let Err(err) = chainload(path, chain_options) {
+            eprintln!("Chainloading \"{}\" failed: {}", path, err);
+        }
+    }
+
+    // Picks the display the splash and kernel use, e.g. "display=1"
+    if let Some(display) = options
+        .split_whitespace()
+        .find_map(|option| option.strip_prefix("display="))
+    {
+        match display.parse() {
+            Ok(index) => {
+                if let Err(err) = uefi::graphics::set_primary(index) {
+                    eprintln!("Warning: unable to use display {}: {}", index, err);
+                }
+            }
+            Err(_) => eprintln!("Warning: invalid display \"{}\"", display),
+        }
+    }
+
+    // The kernel can live on another volume, e.g. "kernel=part=<guid>:\\los\\kernel.elf"
+    let kernel_path = options
+        .split_whitespace()
+        .find_map(|option| option.strip_prefix("kernel="))
+        .unwrap_or(DEFAULT_KERNEL_PATH);
+
+    // The root partition defaults to the boot disk's x86-64 root partition
+    let root_partition = match options
+        .split_whitespace()
+        .find_map(|option| option.strip_prefix("root="))
+    {
+        Some(guid) => Some(guid.parse().context("Invalid root partition")?),
+        None => None,
+    };
+
+    let boot_options = BootOptions {
+        kernel_path,
+        root_partition,
+        device_tree_path: options
+            .split_whitespace()
+            .find_map(|option| option.strip_prefix("dtb=")),
+        initrd_path: options
+            .split_whitespace()
+            .find_map(|option| option.strip_prefix("initrd=")),
+        command_line: kernel_command_line(options),
+    };
+
+    println!(
+        "Firmware: {} revision {:#x} (UEFI {})",
+        uefi::firmware_vendor(),
+        uefi::firmware_revision(),
+        uefi::specification_revision()
+    );
+
+    let mut splash = splash::Splash::new(BOOT_STAGES, verbose);
+    let result = boot(&mut splash, &boot_options);
+    if result.is_err() {
+        splash.disable();
+    }
+    result
+}
+
+fn boot(splash: &mut splash::Splash, options: &BootOptions) -> Result<(), uefi::Error> {
+    let kernel_path = options.kernel_path;
     // Load the kernel
     print!("Loading kernel . . . ");
     let entry: KernelEntry = {
-        let kernel = uefi::file::load_file("kernel.elf")?;
-        unsafe { core::mem::transmute(elf::load_executable(&kernel)?) }
+        let mut kernel = uefi::file::File::open(kernel_path)?;
+        unsafe {
+            core::mem::transmute(
+                elf::load_executable(&mut kernel)
+                    .with_context(format_args!("Failed to load \"{}\"", kernel_path))?,
+            )
+        }
     };
     println!("OK!");
+    match uefi::file::device_path(kernel_path) {
+        Ok(path) => println!("Kernel loaded from {}", path),
+        Err(err) => println!("Kernel device path unavailable: {}", err),
+    }
+    splash.advance();
 
     // Get the graphics mode info
     print!("Getting video mode information . . . ");
-    let graphics_info = uefi::graphics::get_info()?;
+    let graphics_modes = uefi::graphics::get_info()?;
+    let graphics_info = uefi::graphics::GraphicsOutputs {
+        count: graphics_modes.len(),
+        modes: graphics_modes.as_ptr(),
+    };
     println!("OK!");
+    splash.advance();
+
+    let config_tables = uefi::config_table::get_config_tables();
+    for table in &config_tables {
+        match uefi::config_table::table_name(&table.guid) {
+            Some(name) => println!("Found the {} table at {:p}", name, table.address),
+            None => println!("Found table {} at {:p}", table.guid, table.address),
+        }
+    }
+    let config_tables_info = uefi::config_table::ConfigTables {
+        count: config_tables.len(),
+        tables: config_tables.as_ptr(),
+    };
 
     // Get the ACPI RSDP
-    print!("Getting the ACPI RSDP . . . ");
-    let rsdp = uefi::config_table::get_config_table(uefi::config_table::ACPI_20_RSDP_GUID)?;
-    println!("OK!");
+    print!("Getting ACPI tables . . . ");
+    let rsdp = uefi::config_table::get_rsdp()?;
+    println!(
+        "OK! (ACPI {}, revision {})",
+        if rsdp.revision >= 2 { "2.0+" } else { "1.0" },
+        rsdp.revision
+    );
+    if !rsdp.valid {
+        eprintln!("Warning: the ACPI RSDP checksum is invalid");
+    }
 
-    // Get memory info
+    // Without usable tables the kernel gets an empty summary and falls back to its defaults
+    let acpi_info = match uefi::acpi::Tables::read(&rsdp) {
+        Ok(acpi_tables) => {
+            if acpi_tables.used_rsdt && rsdp.revision >= 2 {
+                eprintln!("Warning: the ACPI XSDT is unusable, using the RSDT");
+            }
+            for table in acpi_tables.iter().filter(|table| !table.valid) {
+                eprintln!("Warning: the ACPI {} table is invalid", table.signature);
+            }
+            acpi_tables.summarize()
+        }
+        Err(err) => {
+            eprintln!("Warning: {}", err);
+            uefi::acpi::AcpiInfo::empty()
+        }
+    };
+    println!(
+        "Found {} CPU(s) and {} I/O APIC(s)",
+        acpi_info.cpu_count, acpi_info.io_apic_count
+    );
+    splash.advance();
+
+    // SMBIOS is only informational, so the kernel gets an empty entry point without it
+    print!("Getting SMBIOS information . . . ");
+    let smbios = match uefi::smbios::get_entry_point() {
+        Ok(entry_point) => {
+            println!(
+                "OK! (SMBIOS {}.{})",
+                entry_point.major_version, entry_point.minor_version
+            );
+            if entry_point.valid {
+                log_smbios(&uefi::smbios::Smbios::read(&entry_point));
+            } else {
+                eprintln!("Warning: the SMBIOS entry point checksum is invalid");
+            }
+            entry_point
+        }
+        Err(err) => {
+            println!("unavailable: {}", err);
+            uefi::smbios::EntryPoint::empty()
+        }
+    };
+    splash.advance();
+
+    // Device trees are mostly found on virtual platforms, so a missing one isn't reported
+    print!("Getting the device tree . . . ");
+    let device_tree = match load_device_tree(options, &graphics_modes) {
+        Ok(Some(device_tree)) => {
+            println!("OK! ({} bytes)", device_tree.len());
+            Some(device_tree)
+        }
+        Ok(None) => {
+            println!("none");
+            None
+        }
+        Err(err) => {
+            println!("unavailable: {}", err);
+            None
+        }
+    };
+    let device_tree_ptr = device_tree
+        .as_ref()
+        .map_or(core::ptr::null(), |device_tree| {
+            device_tree.as_ptr() as *const c_void
+        });
+    splash.advance();
+
+    // Not every boot disk has a GPT, so the kernel may have to find its root itself
+    print!("Getting boot disk information . . . ");
+    let boot_disk = match uefi::block::get_boot_disk(options.root_partition) {
+        Ok(boot_disk) => {
+            println!("OK!");
+            boot_disk
+        }
+        Err(err) => {
+            println!("unavailable: {}", err);
+            uefi::block::BootDisk::empty()
+        }
+    };
+    splash.advance();
+
+    // Without the table the kernel has to map runtime services as both writable and executable
+    print!("Getting memory attributes . . . ");
+    let memory_attributes = match uefi::memory::get_memory_attributes() {
+        Ok(table) => {
+            println!("OK! ({} runtime regions)", table.descriptors().count());
+            // Checked now as nothing can be logged once the final memory map is read
+            if let Ok(mmap) = uefi::memory::get_memory_map() {
+                for descriptor in mmap.runtime_conflicts(&table) {
+                    eprintln!(
+                        "Warning: the memory attributes table overlaps type {} memory at {:#x}",
+                        descriptor.memory_type, descriptor.physical_start
+                    );
+                }
+                mmap.free().ok();
+            }
+            Some(table)
+        }
+        Err(err) => {
+            println!("unavailable: {}", err);
+            None
+        }
+    };
+    let memory_attributes_info = memory_attributes
+        .as_ref()
+        .map_or_else(uefi::memory::MemoryAttributes::empty, |table| {
+            table.handoff()
+        });
+    splash.advance();
+
+    save_boot_log();
+
+    // Get memory info. The splash finishes first as drawing it, or switching
+    // to the log on a key press, could change the memory map.
     print!("Getting memory information . . . ");
-    let mmap = uefi::memory::get_memory_map()?;
+    splash.advance();
+    let mut mmap = uefi::memory::get_memory_map()?;
+    if let Some(table) = &memory_attributes {
+        mmap.flag_runtime_regions(table);
+    }
 
     exit_boot_services(mmap.key)?;
 
-    entry(&graphics_info, &mmap, rsdp);
+    entry(
+        &graphics_info,
+        &mmap,
+        &memory_attributes_info,
+        &rsdp,
+        &boot_disk,
+        &acpi_info,
+        &smbios,
+        &config_tables_info,
+        device_tree_ptr,
+    );
 
     loop {
         unsafe { core::arch::asm!("hlt") };
     }
 }
 
+fn chainload(path: &str, options: &str) -> Result<(), uefi::Error> {
+    print!("Loading \"{}\" . . . ", path);
+    let mut image = uefi::image::Image::load(path)?;
+    if !options.is_empty() {
+        image.set_load_options(options)?;
+    }
+    println!("OK!");
+
+    save_boot_log();
+
+    image.start()?;
+    println!("\"{}\" exited", path);
+    Ok(())
+}
+
+fn load_device_tree(
+    options: &BootOptions,
+    graphics_modes: &[uefi::graphics::GraphicsMode],
+) -> Result<Option<Vec<u8>>, uefi::Error> {
+    let mut device_tree = match options.device_tree_path {
+        Some(path) => {
+            let data = uefi::file::load_file(path)?;
+            uefi::fdt::Fdt::parse(&data)
+                .with_context(format_args!("Failed to parse \"{}\"", path))?
+        }
+        None => match uefi::fdt::get_firmware_blob() {
+            Ok(data) => uefi::fdt::Fdt::parse(data)?,
+            Err(_) => return Ok(None),
+        },
+    };
+
+    device_tree.set_bootargs(&options.command_line);
+
+    if let Some(path) = options.initrd_path {
+        let (start, size) = load_initrd(path)?;
+        device_tree.set_initrd(start, start + size);
+    }
+
+    let framebuffer = graphics_modes
+        .iter()
+        .find(|mode| mode.primary)
+        .or_else(|| graphics_modes.first());
+    if let Some(mode) = framebuffer {
+        if let Err(err) = device_tree.set_framebuffer(mode) {
+            eprintln!("Warning: {}", err);
+        }
+    }
+
+    Ok(Some(device_tree.to_bytes()))
+}
+
+// Read straight into pages, which stay allocated for the kernel, as an initrd
+// can be too large for the pool
+fn load_initrd(path: &str) -> Result<(u64, u64), uefi::Error> {
+    let mut file = uefi::file::File::open(path)?;
+    let size = file.size()?;
+    let start = uefi::memory::allocate_any_pages(size as usize)
+        .with_context(format_args!("Failed to allocate memory for \"{}\"", path))?;
+    let initrd = unsafe { core::slice::from_raw_parts_mut(start as *mut u8, size as usize) };
+
+    // Shown in quarters so large initrds don't look stuck
+    let mut shown = 0;
+    let mut progress = |read: u64, total: u64| {
+        let quarter = read * 4 / total.max(1);
+        while shown < quarter {
+            shown += 1;
+            print!("{}% ", shown * 25);
+        }
+    };
+    file.read_at(0, initrd, Some(&mut progress))
+        .with_context(format_args!("Failed to read \"{}\"", path))?;
+
+    Ok((start, size))
+}
+
+// Drops the options the bootloader consumed, so the kernel only sees its own
+fn kernel_command_line(options: &str) -> String {
+    options
+        .split_whitespace()
+        .filter(|option| {
+            *option != "verbose"
+                && !BOOTLOADER_OPTIONS
+                    .iter()
+                    .any(|prefix| option.starts_with(prefix))
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn log_smbios(smbios: &uefi::smbios::Smbios) {
+    if let Some(bios) = &smbios.bios {
+        println!(
+            "BIOS: {} {} ({})",
+            bios.vendor, bios.version, bios.release_date
+        );
+    }
+
+    if let Some(system) = &smbios.system {
+        println!(
+            "System: {} {} {}",
+            system.manufacturer, system.product_name, system.version
+        );
+        if let Some(uuid) = system.uuid {
+            println!("System UUID: {}", uuid);
+        }
+    }
+
+    for device in &smbios.memory_devices {
+        match device.size {
+            Some(0) => continue,
+            Some(size) => print!("Memory: {} MiB", size / 1024 / 1024),
+            None => print!("Memory: unknown size"),
+        }
+        if device.speed != 0 {
+            print!(" at {} MT/s", device.speed);
+        }
+        println!(
+            " in {} {} ({} {})",
+            device.bank_locator, device.locator, device.manufacturer, device.part_number
+        );
+    }
+}
+
+// The boot volume may be read only, so failing to save the log is not fatal
+fn save_boot_log() {
+    if uefi::boot_services_exited() {
+        return;
+    }
+
+    let result = uefi::file::create_dir(BOOT_LOG_DIRECTORY)
+        .and_then(|()| uefi::file::File::create(BOOT_LOG_PATH))
+        .and_then(|mut log| {
+            log.write_all(uefi::console::boot_log())?;
+            log.flush()
+        });
+
+    if let Err(err) = result {
+        eprintln!("Unable to save the boot log: {}", err);
+    }
+}
+
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
-    uefi::println!("{}", info);
+    // A panic while reporting a panic, or after the firmware is gone, can only halt
+    let panicking = unsafe { PANICKING };
+    unsafe { PANICKING = true };
+    if !panicking && !uefi::boot_services_exited() {
+        uefi::console::set_output_enabled(true);
+        eprintln!("{}", info);
+        save_boot_log();
+    }
 
     loop {
         unsafe { core::arch::asm!("hlt") };
@@ -0,0 +1,157 @@
+use uefi::graphics::{BltPixel, GraphicsOutput};
+
+const SPLASH_IMAGES: &[&str] = &["splash.bmp", "splash.qoi"];
+
+const BAR_WIDTH_DIVISOR: usize = 3;
+const BAR_HEIGHT: usize = 8;
+const BAR_MARGIN: usize = 32;
+
+const BACKGROUND: BltPixel = BltPixel {
+    blue: 0,
+    green: 0,
+    red: 0,
+    reserved: 0,
+};
+const BAR_EMPTY: BltPixel = BltPixel {
+    blue: 0x40,
+    green: 0x40,
+    red: 0x40,
+    reserved: 0,
+};
+const BAR_FULL: BltPixel = BltPixel {
+    blue: 0xFF,
+    green: 0xFF,
+    red: 0xFF,
+    reserved: 0,
+};
+
+pub struct Splash {
+    active: bool,
+    // Cached so advancing never allocates, as the last stage runs just
+    // before the memory map is read
+    output: Option<GraphicsOutput>,
+    stages: usize,
+    stage: usize,
+    bar_x: usize,
+    bar_y: usize,
+    bar_width: usize,
+}
+
+impl Splash {
+    pub fn new(stages: usize, verbose: bool) -> Self {
+        let mut splash = Splash {
+            active: false,
+            output: None,
+            stages,
+            stage: 0,
+            bar_x: 0,
+            bar_y: 0,
+            bar_width: 0,
+        };
+
+        if !verbose && splash.draw().is_ok() {
+            splash.active = true;
+            uefi::console::set_output_enabled(false);
+        }
+
+        splash
+    }
+
+    pub fn advance(&mut self) {
+        if !self.active {
+            return;
+        }
+
+        // Any key switches to the log view
+        if uefi::console::key_pressed() {
+            self.disable();
+            return;
+        }
+
+        self.stage += 1;
+        if self.draw_progress().is_err() {
+            self.disable();
+        }
+    }
+
+    pub fn disable(&mut self) {
+        if !self.active {
+            return;
+        }
+
+        self.active = false;
+        uefi::console::set_output_enabled(true);
+        uefi::console::clear_screen().ok();
+    }
+
+    fn draw(&mut self) -> Result<(), uefi::Error> {
+        let image = load_image()?;
+        let output = GraphicsOutput::primary()?;
+        self.output = Some(output);
+        let (width, height) = output.resolution();
+        let (width, height) = (width as usize, height as usize);
+
+        if image.width > width || image.height > height {
+            return Err(uefi::Error::new(
+                uefi::Status::BAD_BUFFER_SIZE,
+                "Splash image is larger than the screen",
+            ));
+        }
+
+        self.bar_width = width / BAR_WIDTH_DIVISOR;
+        self.bar_x = (width - self.bar_width) / 2;
+        self.bar_y = ((height + image.height) / 2 + BAR_MARGIN).min(height - BAR_HEIGHT);
+
+        output.fill_rect(BACKGROUND, 0, 0, width, height)?;
+        output.draw_image(
+            &image.pixels,
+            (width - image.width) / 2,
+            (height - image.height) / 2,
+            image.width,
+            image.height,
+        )?;
+
+        self.draw_progress()
+    }
+
+    fn draw_progress(&self) -> Result<(), uefi::Error> {
+        let output = match &self.output {
+            Some(output) => output,
+            None => {
+                return Err(uefi::Error::new(
+                    uefi::Status::NOT_READY,
+                    "No splash output",
+                ))
+            }
+        };
+        let filled = self.bar_width * self.stage.min(self.stages) / self.stages.max(1);
+
+        if filled > 0 {
+            output.fill_rect(BAR_FULL, self.bar_x, self.bar_y, filled, BAR_HEIGHT)?;
+        }
+        if filled < self.bar_width {
+            output.fill_rect(
+                BAR_EMPTY,
+                self.bar_x + filled,
+                self.bar_y,
+                self.bar_width - filled,
+                BAR_HEIGHT,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn load_image() -> Result<crate::image::Image, uefi::Error> {
+    let mut result = Err(uefi::Error::new(uefi::Status::NOT_FOUND, "No splash image"));
+
+    for path in SPLASH_IMAGES {
+        result = uefi::file::load_file(path).and_then(|data| crate::image::decode(&data));
+        if result.is_ok() {
+            break;
+        }
+    }
+
+    result
+}
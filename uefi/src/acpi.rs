@@ -0,0 +1,475 @@
+use crate::{config_table::Rsdp, efi};
+use alloc::vec::Vec;
+use core::fmt;
+
+pub const MAX_CPUS: usize = 256;
+pub const MAX_IO_APICS: usize = 16;
+pub const MAX_INTERRUPT_OVERRIDES: usize = 32;
+pub const MAX_PCI_SEGMENTS: usize = 16;
+
+const HEADER_SIZE: usize = 36;
+// Anything larger is assumed to be a bad pointer rather than summed
+const MAXIMUM_TABLE_SIZE: usize = 0x1000000;
+
+const RSDP_RSDT_ADDRESS: usize = 16;
+const RSDP_XSDT_ADDRESS: usize = 24;
+
+const MADT_LOCAL_APIC: u8 = 0;
+const MADT_IO_APIC: u8 = 1;
+const MADT_INTERRUPT_OVERRIDE: u8 = 2;
+const MADT_LOCAL_APIC_ADDRESS_OVERRIDE: u8 = 5;
+const MADT_LOCAL_X2APIC: u8 = 9;
+const MADT_PCAT_COMPAT: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature(pub [u8; 4]);
+
+pub struct Table {
+    pub signature: Signature,
+    pub address: *const u8,
+    pub length: usize,
+    pub revision: u8,
+    // False if the checksum failed or the length is implausible
+    pub valid: bool,
+}
+
+// Every table the XSDT (or RSDT) points to, plus the DSDT and FACS
+pub struct Tables {
+    tables: Vec<Table>,
+    // Set when the XSDT was unusable and the RSDT was walked instead
+    pub used_rsdt: bool,
+}
+
+// A compact summary for the kernel, so it doesn't have to parse ACPI just to
+// bring up interrupts and timers. Counts give the used part of each array.
+#[repr(C)]
+pub struct AcpiInfo {
+    pub local_apic_address: u64,
+    pub pic_compatible: bool,
+    pub cpu_count: u32,
+    pub cpus: [Cpu; MAX_CPUS],
+    pub io_apic_count: u32,
+    pub io_apics: [IoApic; MAX_IO_APICS],
+    pub interrupt_override_count: u32,
+    pub interrupt_overrides: [InterruptOverride; MAX_INTERRUPT_OVERRIDES],
+    pub pci_segment_count: u32,
+    pub pci_segments: [PciSegment; MAX_PCI_SEGMENTS],
+    pub fadt: Fadt,
+    pub hpet: Hpet,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Cpu {
+    pub processor_uid: u32,
+    pub apic_id: u32,
+    // Bit 0 is enabled, bit 1 is online capable
+    pub flags: u32,
+    pub x2apic: bool,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct IoApic {
+    pub id: u8,
+    pub address: u32,
+    pub gsi_base: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct InterruptOverride {
+    pub bus: u8,
+    pub source: u8,
+    pub gsi: u32,
+    pub flags: u16,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct PciSegment {
+    pub base_address: u64,
+    pub segment: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct GenericAddress {
+    pub address_space: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Fadt {
+    pub present: bool,
+    pub dsdt: u64,
+    pub facs: u64,
+    pub sci_interrupt: u16,
+    pub smi_command: u32,
+    pub acpi_enable: u8,
+    pub acpi_disable: u8,
+    pub pm1a_event_block: u64,
+    pub pm1a_control_block: u64,
+    pub pm_timer_block: u64,
+    pub pm_timer_length: u8,
+    pub century: u8,
+    pub boot_architecture_flags: u16,
+    pub flags: u32,
+    pub reset_register: GenericAddress,
+    pub reset_value: u8,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Hpet {
+    pub present: bool,
+    pub address: GenericAddress,
+    pub number: u8,
+    pub minimum_tick: u16,
+}
+
+impl Tables {
+    pub fn read(rsdp: &Rsdp) -> Result<Self, crate::Error> {
+        let rsdp_data = rsdp.address as *const u8;
+        let xsdt = if rsdp.revision >= 2 {
+            unsafe { core::ptr::read_unaligned(rsdp_data.add(RSDP_XSDT_ADDRESS) as *const u64) }
+        } else {
+            0
+        };
+        let rsdt =
+            unsafe { core::ptr::read_unaligned(rsdp_data.add(RSDP_RSDT_ADDRESS) as *const u32) };
+
+        // The XSDT is preferred as it can point above 4 GiB
+        let (root, entry_size, used_rsdt) = match unsafe { Table::new(xsdt as *const u8) } {
+            Some(table) if table.valid && table.signature == Signature(*b"XSDT") => {
+                (table, 8, false)
+            }
+            _ => match unsafe { Table::new(rsdt as usize as *const u8) } {
+                Some(table) if table.valid && table.signature == Signature(*b"RSDT") => {
+                    (table, 4, true)
+                }
+                _ => {
+                    return Err(crate::Error::new(
+                        efi::STATUS::NOT_FOUND,
+                        "Failed to find a valid XSDT or RSDT",
+                    ))
+                }
+            },
+        };
+
+        let mut tables = Vec::new();
+        for entry in root.data()[HEADER_SIZE..].chunks_exact(entry_size) {
+            let address = match entry_size {
+                8 => read_u64(entry, 0),
+                _ => read_u32(entry, 0) as u64,
+            };
+
+            if let Some(table) = unsafe { Table::new(address as usize as *const u8) } {
+                tables.push(table);
+            }
+        }
+
+        // The DSDT and FACS are only reachable through the FADT
+        let mut extra = Vec::new();
+        if let Some(fadt) = tables.iter().find(|table| table.is_valid(b"FACP")) {
+            let summary = Fadt::parse(fadt.data());
+            for address in [summary.dsdt, summary.facs] {
+                if let Some(table) = unsafe { Table::new(address as usize as *const u8) } {
+                    extra.push(table);
+                }
+            }
+        }
+        tables.extend(extra);
+        tables.insert(0, root);
+
+        Ok(Tables { tables, used_rsdt })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Table> {
+        self.tables.iter()
+    }
+
+    // Finds the first valid table with `signature`
+    pub fn find(&self, signature: &[u8; 4]) -> Option<&Table> {
+        self.tables.iter().find(|table| table.is_valid(signature))
+    }
+
+    pub fn summarize(&self) -> AcpiInfo {
+        let mut info = AcpiInfo::empty();
+
+        if let Some(madt) = self.find(b"APIC") {
+            info.parse_madt(madt.data());
+        }
+
+        if let Some(fadt) = self.find(b"FACP") {
+            info.fadt = Fadt::parse(fadt.data());
+        }
+
+        if let Some(hpet) = self.find(b"HPET") {
+            let data = hpet.data();
+            if data.len() >= 56 {
+                info.hpet = Hpet {
+                    present: true,
+                    address: read_generic_address(data, 40),
+                    number: data[52],
+                    minimum_tick: read_u16(data, 53),
+                };
+            }
+        }
+
+        if let Some(mcfg) = self.find(b"MCFG") {
+            // Entries start after 8 reserved bytes
+            for entry in mcfg.data().get(44..).unwrap_or(&[]).chunks_exact(16) {
+                let count = info.pci_segment_count as usize;
+                if count == MAX_PCI_SEGMENTS {
+                    break;
+                }
+
+                info.pci_segments[count] = PciSegment {
+                    base_address: read_u64(entry, 0),
+                    segment: read_u16(entry, 8),
+                    start_bus: entry[10],
+                    end_bus: entry[11],
+                };
+                info.pci_segment_count += 1;
+            }
+        }
+
+        info
+    }
+}
+
+impl Table {
+    // Returns `None` for null pointers
+    unsafe fn new(address: *const u8) -> Option<Table> {
+        if address.is_null() {
+            return None;
+        }
+
+        let header = core::slice::from_raw_parts(address, HEADER_SIZE);
+        let mut signature = [0; 4];
+        signature.copy_from_slice(&header[..4]);
+
+        // The FACS has no checksum or revision in the usual place
+        let facs = signature == *b"FACS";
+        let length = read_u32(header, 4) as usize;
+        let plausible =
+            (if facs { 64 } else { HEADER_SIZE }..=MAXIMUM_TABLE_SIZE).contains(&length);
+
+        let valid = plausible
+            && (facs
+                || core::slice::from_raw_parts(address, length)
+                    .iter()
+                    .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+                    == 0);
+
+        Some(Table {
+            signature: Signature(signature),
+            address,
+            length: if plausible { length } else { HEADER_SIZE },
+            revision: if facs { 0 } else { header[8] },
+            valid,
+        })
+    }
+
+    pub fn data(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.address, self.length) }
+    }
+
+    fn is_valid(&self, signature: &[u8; 4]) -> bool {
+        self.valid && self.signature.0 == *signature
+    }
+}
+
+impl AcpiInfo {
+    pub fn empty() -> Self {
+        AcpiInfo {
+            local_apic_address: 0,
+            pic_compatible: false,
+            cpu_count: 0,
+            cpus: [Cpu::default(); MAX_CPUS],
+            io_apic_count: 0,
+            io_apics: [IoApic::default(); MAX_IO_APICS],
+            interrupt_override_count: 0,
+            interrupt_overrides: [InterruptOverride::default(); MAX_INTERRUPT_OVERRIDES],
+            pci_segment_count: 0,
+            pci_segments: [PciSegment::default(); MAX_PCI_SEGMENTS],
+            fadt: Fadt::default(),
+            hpet: Hpet::default(),
+        }
+    }
+
+    fn parse_madt(&mut self, data: &[u8]) {
+        if data.len() < 44 {
+            return;
+        }
+
+        self.local_apic_address = read_u32(data, 36) as u64;
+        self.pic_compatible = read_u32(data, 40) & MADT_PCAT_COMPAT != 0;
+
+        let mut offset = 44;
+        while offset + 2 <= data.len() {
+            let entry_type = data[offset];
+            let length = data[offset + 1] as usize;
+            if length < 2 || offset + length > data.len() {
+                break;
+            }
+            let entry = &data[offset..offset + length];
+            offset += length;
+
+            match entry_type {
+                MADT_LOCAL_APIC if length >= 8 => self.add_cpu(Cpu {
+                    processor_uid: entry[2] as u32,
+                    apic_id: entry[3] as u32,
+                    flags: read_u32(entry, 4),
+                    x2apic: false,
+                }),
+                MADT_LOCAL_X2APIC if length >= 16 => self.add_cpu(Cpu {
+                    processor_uid: read_u32(entry, 12),
+                    apic_id: read_u32(entry, 4),
+                    flags: read_u32(entry, 8),
+                    x2apic: true,
+                }),
+                MADT_IO_APIC if length >= 12 => {
+                    let count = self.io_apic_count as usize;
+                    if count < MAX_IO_APICS {
+                        self.io_apics[count] = IoApic {
+                            id: entry[2],
+                            address: read_u32(entry, 4),
+                            gsi_base: read_u32(entry, 8),
+                        };
+                        self.io_apic_count += 1;
+                    }
+                }
+                MADT_INTERRUPT_OVERRIDE if length >= 10 => {
+                    let count = self.interrupt_override_count as usize;
+                    if count < MAX_INTERRUPT_OVERRIDES {
+                        self.interrupt_overrides[count] = InterruptOverride {
+                            bus: entry[2],
+                            source: entry[3],
+                            gsi: read_u32(entry, 4),
+                            flags: read_u16(entry, 8),
+                        };
+                        self.interrupt_override_count += 1;
+                    }
+                }
+                MADT_LOCAL_APIC_ADDRESS_OVERRIDE if length >= 12 => {
+                    self.local_apic_address = read_u64(entry, 4);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn add_cpu(&mut self, cpu: Cpu) {
+        let count = self.cpu_count as usize;
+        if count < MAX_CPUS {
+            self.cpus[count] = cpu;
+            self.cpu_count += 1;
+        }
+    }
+}
+
+impl Fadt {
+    // Older FADTs are shorter, so the 64-bit fields are only used when present
+    fn parse(data: &[u8]) -> Fadt {
+        let u8_at = |offset: usize| data.get(offset).copied().unwrap_or(0);
+        let u16_at = |offset: usize| {
+            if data.len() >= offset + 2 {
+                read_u16(data, offset)
+            } else {
+                0
+            }
+        };
+        let u32_at = |offset: usize| {
+            if data.len() >= offset + 4 {
+                read_u32(data, offset)
+            } else {
+                0
+            }
+        };
+        let u64_at = |offset: usize| {
+            if data.len() >= offset + 8 {
+                read_u64(data, offset)
+            } else {
+                0
+            }
+        };
+        // `offset` is that of the 64-bit address, within a generic address for blocks
+        let extended = |offset: usize, legacy: u32| match u64_at(offset) {
+            0 => legacy as u64,
+            address => address,
+        };
+
+        Fadt {
+            present: true,
+            dsdt: extended(140, u32_at(40)),
+            facs: extended(132, u32_at(36)),
+            sci_interrupt: u16_at(46),
+            smi_command: u32_at(48),
+            acpi_enable: u8_at(52),
+            acpi_disable: u8_at(53),
+            pm1a_event_block: extended(152, u32_at(56)),
+            pm1a_control_block: extended(176, u32_at(64)),
+            pm_timer_block: extended(212, u32_at(76)),
+            pm_timer_length: u8_at(91),
+            century: u8_at(108),
+            boot_architecture_flags: u16_at(109),
+            flags: u32_at(112),
+            reset_register: if data.len() >= 128 {
+                read_generic_address(data, 116)
+            } else {
+                GenericAddress::default()
+            },
+            reset_value: u8_at(128),
+        }
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            let c = if byte.is_ascii_graphic() {
+                byte as char
+            } else {
+                '?'
+            };
+            fmt::Write::write_char(f, c)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn read_generic_address(data: &[u8], offset: usize) -> GenericAddress {
+    GenericAddress {
+        address_space: data[offset],
+        bit_width: data[offset + 1],
+        bit_offset: data[offset + 2],
+        access_size: data[offset + 3],
+        address: read_u64(data, offset + 4),
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    read_u32(data, offset) as u64 | (read_u32(data, offset + 4) as u64) << 32
+}
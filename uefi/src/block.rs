@@ -0,0 +1,269 @@
+use crate::{
+    config_table::GUID,
+    device_path::{DevicePath, Node, PartitionSignature},
+    efi, gpt, ResultExt,
+};
+use alloc::{vec, vec::Vec};
+use core::ptr::null;
+
+// Discoverable Partitions Specification type for an x86-64 root partition
+pub const ROOT_PARTITION_TYPE_GUID: GUID = GUID {
+    a: 0x4F68BCE3,
+    b: 0xE8CD,
+    c: 0x4DB1,
+    d: [0x96, 0xE7, 0xFB, 0xCA, 0xF9, 0x84, 0xB7, 0x09],
+};
+
+const UNKNOWN_GUID: GUID = GUID {
+    a: 0,
+    b: 0,
+    c: 0,
+    d: [0; 8],
+};
+
+// Passed to the kernel, unknown GUIDs are left as zero
+#[repr(C)]
+pub struct BootDisk {
+    pub disk_guid: GUID,
+    pub boot_partition_guid: GUID,
+    pub root_partition_guid: GUID,
+    pub block_size: u32,
+    pub last_block: u64,
+}
+
+pub struct BlockDevice {
+    handle: efi::HANDLE,
+    block_io: *const efi::BLOCK_IO_PROTOCOL,
+    disk_io: *const efi::DISK_IO_PROTOCOL,
+}
+
+// Block I/O requires buffers aligned to the media's `io_align`, but pool
+// allocations are only 8 byte aligned, so this over-allocates and aligns itself
+struct AlignedBuffer {
+    data: Vec<u8>,
+    offset: usize,
+    size: usize,
+}
+
+pub fn block_devices() -> Result<Vec<BlockDevice>, crate::Error> {
+    let handles = crate::locate_handles(&efi::BLOCK_IO_PROTOCOL_GUID)?;
+
+    let mut devices = Vec::with_capacity(handles.len());
+    for handle in handles {
+        devices.push(BlockDevice::open(handle)?);
+    }
+
+    Ok(devices)
+}
+
+// Whole disks with media present, skipping the partitions on them
+pub fn disks() -> Result<Vec<BlockDevice>, crate::Error> {
+    Ok(block_devices()?
+        .into_iter()
+        .filter(|device| !device.is_partition() && device.media_present())
+        .collect())
+}
+
+pub fn get_boot_disk(root_partition: Option<GUID>) -> Result<BootDisk, crate::Error> {
+    let boot_path = DevicePath::for_handle(crate::file::boot_device())?;
+
+    // The boot volume's path is the disk's path followed by a hard drive node
+    let mut boot_partition_guid = UNKNOWN_GUID;
+    for node in boot_path.nodes() {
+        if let Node::HardDrive {
+            signature: PartitionSignature::Gpt(guid),
+            ..
+        } = node.parse()
+        {
+            boot_partition_guid = guid;
+        }
+    }
+
+    let disk = disks()?
+        .into_iter()
+        .find(|disk| {
+            disk.device_path()
+                .map(|path| boot_path.starts_with(&path))
+                .unwrap_or(false)
+        })
+        .ok_or_else(|| crate::Error::new(efi::STATUS::NOT_FOUND, "Failed to find the boot disk"))?;
+
+    let table = gpt::Gpt::read(&disk).context("Failed to read the boot disk")?;
+
+    let root_partition_guid = root_partition
+        .or_else(|| {
+            table
+                .partitions
+                .iter()
+                .find(|partition| partition.type_guid == ROOT_PARTITION_TYPE_GUID)
+                .map(|partition| partition.unique_guid)
+        })
+        .unwrap_or(UNKNOWN_GUID);
+
+    Ok(BootDisk {
+        disk_guid: table.disk_guid,
+        boot_partition_guid,
+        root_partition_guid,
+        block_size: disk.block_size(),
+        last_block: disk.last_block(),
+    })
+}
+
+impl BootDisk {
+    pub fn empty() -> Self {
+        BootDisk {
+            disk_guid: UNKNOWN_GUID,
+            boot_partition_guid: UNKNOWN_GUID,
+            root_partition_guid: UNKNOWN_GUID,
+            block_size: 0,
+            last_block: 0,
+        }
+    }
+}
+
+impl BlockDevice {
+    pub fn open(handle: efi::HANDLE) -> Result<Self, crate::Error> {
+        let block_io = crate::handle_protocol(handle, &efi::BLOCK_IO_PROTOCOL_GUID)
+            .context("Failed to open block device")?;
+
+        // Disk I/O is optional, reads fall back to whole blocks without it
+        let disk_io = crate::handle_protocol(handle, &efi::DISK_IO_PROTOCOL_GUID).unwrap_or(null());
+
+        Ok(BlockDevice {
+            handle,
+            block_io,
+            disk_io,
+        })
+    }
+
+    pub fn handle(&self) -> efi::HANDLE {
+        self.handle
+    }
+
+    pub fn device_path(&self) -> Result<DevicePath, crate::Error> {
+        DevicePath::for_handle(self.handle)
+    }
+
+    pub fn block_size(&self) -> u32 {
+        self.media().block_size
+    }
+
+    pub fn last_block(&self) -> u64 {
+        self.media().last_block
+    }
+
+    pub fn size(&self) -> u64 {
+        (self.last_block() + 1) * self.block_size() as u64
+    }
+
+    pub fn is_partition(&self) -> bool {
+        self.media().logical_partition != efi::FALSE
+    }
+
+    pub fn is_removable(&self) -> bool {
+        self.media().removable_media != efi::FALSE
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.media().read_only != efi::FALSE
+    }
+
+    pub fn media_present(&self) -> bool {
+        self.media().media_present != efi::FALSE
+    }
+
+    // `buffer` must be a whole number of blocks
+    pub fn read_blocks(&self, lba: u64, buffer: &mut [u8]) -> Result<(), crate::Error> {
+        let media = self.media();
+        if buffer.len() as u64 % self.checked_block_size()? != 0 {
+            return Err(crate::Error::new(
+                efi::STATUS::BAD_BUFFER_SIZE,
+                "Read is not a whole number of blocks",
+            ));
+        }
+
+        if buffer.is_empty() {
+            return Ok(());
+        }
+
+        let mut aligned = AlignedBuffer::new(buffer.len(), media.io_align as usize);
+        let status = unsafe {
+            ((*self.block_io).read_blocks)(
+                self.block_io,
+                media.media_id,
+                lba,
+                buffer.len(),
+                aligned.as_mut_ptr() as *mut efi::VOID,
+            )
+        };
+        status.to_result("Failed to read blocks")?;
+
+        buffer.copy_from_slice(aligned.as_slice());
+        Ok(())
+    }
+
+    // Reads from any byte offset, using disk I/O when the firmware has it
+    pub fn read(&self, offset: u64, buffer: &mut [u8]) -> Result<(), crate::Error> {
+        if offset + buffer.len() as u64 > self.size() {
+            return Err(crate::Error::new(
+                efi::STATUS::INVALID_PARAMETER,
+                "Read is past the end of the device",
+            ));
+        }
+
+        if !self.disk_io.is_null() {
+            let status = unsafe {
+                ((*self.disk_io).read_disk)(
+                    self.disk_io,
+                    self.media().media_id,
+                    offset,
+                    buffer.len(),
+                    buffer.as_mut_ptr() as *mut efi::VOID,
+                )
+            };
+            return status.to_result("Failed to read disk");
+        }
+
+        let block_size = self.checked_block_size()?;
+        let first = offset / block_size;
+        let last = (offset + buffer.len() as u64).div_ceil(block_size);
+        let mut blocks = alloc::vec![0; ((last - first) * block_size) as usize];
+        self.read_blocks(first, &mut blocks)?;
+
+        let start = (offset - first * block_size) as usize;
+        buffer.copy_from_slice(&blocks[start..start + buffer.len()]);
+        Ok(())
+    }
+
+    // Some firmware reports zero for media it can't read
+    fn checked_block_size(&self) -> Result<u64, crate::Error> {
+        match self.block_size() {
+            0 => Err(crate::Error::new(
+                efi::STATUS::DEVICE_ERROR,
+                "Device has a block size of zero",
+            )),
+            block_size => Ok(block_size as u64),
+        }
+    }
+
+    fn media(&self) -> &efi::BLOCK_IO_MEDIA {
+        unsafe { &*(*self.block_io).media }
+    }
+}
+
+impl AlignedBuffer {
+    fn new(size: usize, align: usize) -> Self {
+        let align = align.max(1).next_power_of_two();
+        let data = vec![0; size + align - 1];
+        let offset = data.as_ptr().align_offset(align);
+        AlignedBuffer { data, offset, size }
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.data[self.offset..].as_mut_ptr()
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.data[self.offset..self.offset + self.size]
+    }
+}
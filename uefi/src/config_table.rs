@@ -1,6 +1,7 @@
 use core::{ffi::c_void, ptr::null};
 
 use crate::efi;
+use alloc::vec::Vec;
 
 pub type GUID = efi::GUID;
 
@@ -21,6 +22,131 @@ pub const ACPI_20_RSDP_GUID: GUID = GUID {
     d: [0xBC, 0x22, 0x00, 0x80, 0xC7, 0x3C, 0x88, 0x81],
 };
 
+pub const ACPI_10_RSDP_GUID: GUID = GUID {
+    a: 0xEB9D2D30,
+    b: 0x2D88,
+    c: 0x11D3,
+    d: [0x9A, 0x16, 0x00, 0x90, 0x27, 0x3F, 0xC1, 0x4D],
+};
+
+pub const SMBIOS_TABLE_GUID: GUID = GUID {
+    a: 0xEB9D2D31,
+    b: 0x2D88,
+    c: 0x11D3,
+    d: [0x9A, 0x16, 0x00, 0x90, 0x27, 0x3F, 0xC1, 0x4D],
+};
+
+pub const SMBIOS3_TABLE_GUID: GUID = GUID {
+    a: 0xF2FD1544,
+    b: 0x9794,
+    c: 0x4A2C,
+    d: [0x99, 0x2E, 0xE5, 0xBB, 0xCF, 0x20, 0xE3, 0x94],
+};
+
+pub const DEVICE_TREE_GUID: GUID = GUID {
+    a: 0xB1B621D5,
+    b: 0xF19C,
+    c: 0x41A5,
+    d: [0x83, 0x0B, 0xD9, 0x15, 0x2C, 0x69, 0xAA, 0xE0],
+};
+
+pub const MEMORY_ATTRIBUTES_TABLE_GUID: GUID = GUID {
+    a: 0xDCFA911D,
+    b: 0x26EB,
+    c: 0x469F,
+    d: [0xA2, 0x20, 0x38, 0xB7, 0xDC, 0x46, 0x12, 0x20],
+};
+
+pub const PROPERTIES_TABLE_GUID: GUID = GUID {
+    a: 0x880AACA3,
+    b: 0x4ADC,
+    c: 0x4A04,
+    d: [0x90, 0x79, 0xB7, 0x47, 0x34, 0x08, 0x25, 0xE5],
+};
+
+pub const SYSTEM_RESOURCE_TABLE_GUID: GUID = GUID {
+    a: 0xB122A263,
+    b: 0x3661,
+    c: 0x4F68,
+    d: [0x99, 0x29, 0x78, 0xF8, 0xB0, 0xD6, 0x21, 0x80],
+};
+
+pub const DEBUG_IMAGE_INFO_TABLE_GUID: GUID = GUID {
+    a: 0x49152E77,
+    b: 0x1ADA,
+    c: 0x4764,
+    d: [0xB7, 0xA2, 0x7A, 0xFE, 0xFE, 0xD9, 0x5E, 0x8B],
+};
+
+pub const LZMA_COMPRESS_GUID: GUID = GUID {
+    a: 0xEE4E5898,
+    b: 0x3914,
+    c: 0x4259,
+    d: [0x9D, 0x6E, 0xDC, 0x7B, 0xD7, 0x94, 0x03, 0xCF],
+};
+
+pub const HOB_LIST_GUID: GUID = GUID {
+    a: 0x7739F24C,
+    b: 0x93D7,
+    c: 0x11D4,
+    d: [0x9A, 0x3A, 0x00, 0x90, 0x27, 0x3F, 0xC1, 0x4D],
+};
+
+pub const RT_PROPERTIES_TABLE_GUID: GUID = GUID {
+    a: 0xEB66918A,
+    b: 0x7EEF,
+    c: 0x402A,
+    d: [0x84, 0x2E, 0x93, 0x1D, 0x21, 0xC3, 0x8A, 0xE9],
+};
+
+const KNOWN_TABLES: [(GUID, &str); 12] = [
+    (ACPI_20_RSDP_GUID, "ACPI 2.0"),
+    (ACPI_10_RSDP_GUID, "ACPI 1.0"),
+    (SMBIOS3_TABLE_GUID, "SMBIOS 3"),
+    (SMBIOS_TABLE_GUID, "SMBIOS"),
+    (DEVICE_TREE_GUID, "Device tree"),
+    (MEMORY_ATTRIBUTES_TABLE_GUID, "Memory attributes"),
+    (PROPERTIES_TABLE_GUID, "Properties"),
+    (SYSTEM_RESOURCE_TABLE_GUID, "ESRT"),
+    (DEBUG_IMAGE_INFO_TABLE_GUID, "Debug image info"),
+    (LZMA_COMPRESS_GUID, "LZMA compress"),
+    (HOB_LIST_GUID, "HOB list"),
+    (RT_PROPERTIES_TABLE_GUID, "RT properties"),
+];
+
+const RSDP_SIGNATURE: &[u8] = b"RSD PTR ";
+const RSDP_V1_LENGTH: usize = 20;
+const RSDP_V2_LENGTH: usize = 36;
+const RSDP_REVISION: usize = 15;
+const RSDP_LENGTH: usize = 20;
+
+// Anything longer is assumed to be garbage rather than summed
+const RSDP_MAXIMUM_LENGTH: usize = 4096;
+
+// Passed to the kernel. The revision is 0 for ACPI 1.0, which only has an
+// RSDT, and 2 or later when there is also an XSDT.
+#[repr(C)]
+pub struct Rsdp {
+    pub address: *const c_void,
+    pub revision: u8,
+    // False if a checksum failed, which the kernel may choose to ignore
+    pub valid: bool,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ConfigTable {
+    pub guid: GUID,
+    pub address: *const c_void,
+}
+
+// Passed to the kernel so it can use any table without a bootloader change
+#[repr(C)]
+pub struct ConfigTables {
+    pub count: usize,
+    pub tables: *const ConfigTable,
+}
+
 pub fn initialize(system_table: &efi::SYSTEM_TABLE) {
     unsafe {
         CONFIGURATION_TABLE = ConfigurationTable {
@@ -30,21 +156,99 @@ pub fn initialize(system_table: &efi::SYSTEM_TABLE) {
     }
 }
 
-pub fn get_config_table(guid: GUID) -> Result<*const c_void, crate::Error> {
-    let mut ect = unsafe { CONFIGURATION_TABLE.table };
-    let num_tables = unsafe { CONFIGURATION_TABLE.num_tables };
-    let mut i = 0;
-    while i < num_tables {
-        if unsafe { (*ect).vendor_guid == guid } {
-            return Ok(unsafe { (*ect).vendor_table });
+// Every table the firmware installed, in its order
+pub fn config_tables() -> impl Iterator<Item = ConfigTable> {
+    let (table, num_tables) =
+        unsafe { (CONFIGURATION_TABLE.table, CONFIGURATION_TABLE.num_tables) };
+    (0..num_tables).map(move |i| {
+        let entry = unsafe { &*table.add(i) };
+        ConfigTable {
+            guid: entry.vendor_guid,
+            address: entry.vendor_table,
         }
+    })
+}
 
-        i += 1;
-        ect = unsafe { ect.offset(1) };
+pub fn get_config_table(guid: GUID) -> Result<*const c_void, crate::Error> {
+    match config_tables().find(|table| table.guid == guid) {
+        Some(table) => Ok(table.address),
+        None => Err(crate::error!(
+            efi::STATUS::NOT_FOUND,
+            "Failed to get table {}",
+            guid
+        )),
     }
+}
+
+// Returns a readable name for the tables the bootloader knows about
+pub fn table_name(guid: &GUID) -> Option<&'static str> {
+    KNOWN_TABLES
+        .iter()
+        .find(|(known, _)| known == guid)
+        .map(|(_, name)| *name)
+}
 
-    Err(crate::Error::new(
+// Copied so the kernel gets the list as it was when the bootloader ran
+pub fn get_config_tables() -> Vec<ConfigTable> {
+    config_tables().collect()
+}
+
+// Prefers the ACPI 2.0 table, falling back to the ACPI 1.0 one on older firmware
+pub fn get_rsdp() -> Result<Rsdp, crate::Error> {
+    let mut result = Err(crate::Error::new(
         efi::STATUS::NOT_FOUND,
-        "Failed to get table",
-    ))
+        "Failed to find the ACPI RSDP",
+    ));
+
+    for guid in [ACPI_20_RSDP_GUID, ACPI_10_RSDP_GUID] {
+        let address = match get_config_table(guid) {
+            Ok(address) if !address.is_null() => address,
+            _ => continue,
+        };
+
+        result = unsafe { parse_rsdp(address) };
+        if result.is_ok() {
+            break;
+        }
+    }
+
+    result
+}
+
+// A bad signature means this isn't an RSDP at all, but bad checksums only
+// mark it as invalid
+unsafe fn parse_rsdp(address: *const c_void) -> Result<Rsdp, crate::Error> {
+    let header = core::slice::from_raw_parts(address as *const u8, RSDP_V1_LENGTH);
+    if &header[..RSDP_SIGNATURE.len()] != RSDP_SIGNATURE {
+        return Err(crate::Error::new(
+            efi::STATUS::COMPROMISED_DATA,
+            "Invalid ACPI RSDP signature",
+        ));
+    }
+
+    let revision = header[RSDP_REVISION];
+    let mut valid = checksum(header);
+
+    // The extended checksum covers the whole table, including the first part
+    if revision >= 2 {
+        let length = u32::from_le_bytes([
+            header[RSDP_LENGTH],
+            header[RSDP_LENGTH + 1],
+            header[RSDP_LENGTH + 2],
+            header[RSDP_LENGTH + 3],
+        ]) as usize;
+
+        valid &= (RSDP_V2_LENGTH..=RSDP_MAXIMUM_LENGTH).contains(&length)
+            && checksum(core::slice::from_raw_parts(address as *const u8, length));
+    }
+
+    Ok(Rsdp {
+        address,
+        revision,
+        valid,
+    })
+}
+
+fn checksum(data: &[u8]) -> bool {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0
 }
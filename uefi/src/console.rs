@@ -1,16 +1,133 @@
-use crate::efi;
-use alloc::vec::Vec;
-use core::fmt::{self, Write};
+use crate::{
+    efi,
+    string::{self, CStr16, Ucs2Policy},
+};
+use core::{
+    fmt::{self, Write},
+    ptr::null,
+};
+
+const OUTPUT_BUFFER_SIZE: usize = 128;
+const BOOT_LOG_SIZE: usize = 0x4000;
 
 pub struct Console(
     &'static efi::SIMPLE_TEXT_OUTPUT_PROTOCOL,
     *const efi::SIMPLE_TEXT_OUTPUT_PROTOCOL,
+    bool,
 );
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Black = efi::BLACK as isize,
+    Blue = efi::BLUE as isize,
+    Green = efi::GREEN as isize,
+    Cyan = efi::CYAN as isize,
+    Red = efi::RED as isize,
+    Magenta = efi::MAGENTA as isize,
+    Brown = efi::BROWN as isize,
+    LightGray = efi::LIGHTGRAY as isize,
+    DarkGray = efi::DARKGRAY as isize,
+    LightBlue = efi::LIGHTBLUE as isize,
+    LightGreen = efi::LIGHTGREEN as isize,
+    LightCyan = efi::LIGHTCYAN as isize,
+    LightRed = efi::LIGHTRED as isize,
+    LightMagenta = efi::LIGHTMAGENTA as isize,
+    Yellow = efi::YELLOW as isize,
+    White = efi::WHITE as isize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ConsoleMode {
+    pub max_mode: usize,
+    pub mode: usize,
+    pub foreground: usize,
+    pub background: usize,
+    pub cursor_column: usize,
+    pub cursor_row: usize,
+    pub cursor_visible: bool,
+}
+
+struct BootLog {
+    buffer: [u8; BOOT_LOG_SIZE],
+    length: usize,
+}
+
 static mut STANDARD_OUTPUT: Option<Console> = None;
+static mut BOOT_LOG: BootLog = BootLog {
+    buffer: [0; BOOT_LOG_SIZE],
+    length: 0,
+};
+static mut STANDARD_ERROR: Option<Console> = None;
+static mut STANDARD_INPUT: *const efi::SIMPLE_TEXT_INPUT_PROTOCOL = null();
+static mut OUTPUT_ENABLED: bool = true;
+static mut UCS2_POLICY: Ucs2Policy = Ucs2Policy::Replace;
 
 pub fn initialize(system_table: &efi::SYSTEM_TABLE) -> Result<(), crate::Error> {
     let stdout = Console::new(system_table.console_out)?;
+    stdout.set_largest_mode().ok();
+
+    unsafe {
+        STANDARD_OUTPUT = Some(stdout);
+        STANDARD_INPUT = system_table.console_in;
+
+        // Standard error shares the screen with standard output, so it is not cleared
+        if !system_table.standard_error.is_null() {
+            STANDARD_ERROR = Some(Console::open(system_table.standard_error));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn set_output_enabled(enabled: bool) {
+    unsafe { OUTPUT_ENABLED = enabled };
+}
+
+pub fn set_ucs2_policy(policy: Ucs2Policy) {
+    unsafe { UCS2_POLICY = policy };
+}
+
+// Everything printed so far, kept even while output is disabled
+pub fn boot_log() -> &'static [u8] {
+    unsafe { &BOOT_LOG.buffer[..BOOT_LOG.length] }
+}
+
+pub fn standard_output() -> Option<&'static Console> {
+    unsafe { STANDARD_OUTPUT.as_ref() }
+}
+
+pub fn standard_error() -> Option<&'static Console> {
+    unsafe { STANDARD_ERROR.as_ref().or(STANDARD_OUTPUT.as_ref()) }
+}
+
+pub fn clear_screen() -> Result<(), crate::Error> {
+    match unsafe { &STANDARD_OUTPUT } {
+        None => Ok(()),
+        Some(console) => {
+            console.clear_screen()?;
+            console.set_cursor_pos(0, 0)
+        }
+    }
+}
+
+pub fn key_pressed() -> bool {
+    let stdin = unsafe { STANDARD_INPUT };
+    if stdin.is_null() {
+        return false;
+    }
+
+    let mut key = efi::INPUT_KEY {
+        scan_code: 0,
+        unicode_char: 0,
+    };
+    let status = unsafe { ((*stdin).read_key_stroke)(stdin, &mut key) };
+    status.to_result("Failed to read key").is_ok()
+}
+
+pub(crate) fn set_output(
+    simple_text_output_interface: *const efi::SIMPLE_TEXT_OUTPUT_PROTOCOL,
+) -> Result<(), crate::Error> {
+    let stdout = Console::new(simple_text_output_interface)?;
 
     unsafe { STANDARD_OUTPUT = Some(stdout) };
 
@@ -19,9 +136,39 @@ pub fn initialize(system_table: &efi::SYSTEM_TABLE) -> Result<(), crate::Error>
 
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
+    unsafe { BOOT_LOG.write_fmt(args).ok() };
+
+    if !unsafe { OUTPUT_ENABLED } {
+        return;
+    }
+
     match unsafe { &mut STANDARD_OUTPUT } {
         None => {}
-        Some(console) => console.write_fmt(args).unwrap(),
+        Some(console) => {
+            console.write_fmt(args).ok();
+        }
+    }
+}
+
+#[doc(hidden)]
+pub fn _eprint(args: fmt::Arguments) {
+    unsafe { BOOT_LOG.write_fmt(args).ok() };
+
+    // Warnings go to the log rather than over the splash, which is disabled
+    // before anything fatal is printed
+    if !unsafe { OUTPUT_ENABLED } {
+        return;
+    }
+
+    match unsafe { &mut STANDARD_ERROR } {
+        Some(console) => {
+            console.write_fmt(args).ok();
+        }
+        None => {
+            if let Some(console) = unsafe { &mut STANDARD_OUTPUT } {
+                console.write_fmt(args).ok();
+            }
+        }
     }
 }
 
@@ -29,10 +176,7 @@ impl Console {
     pub fn new(
         simple_text_output_interface: *const efi::SIMPLE_TEXT_OUTPUT_PROTOCOL,
     ) -> Result<Self, crate::Error> {
-        let console = Console(
-            crate::from_pointer(simple_text_output_interface),
-            simple_text_output_interface,
-        );
+        let console = Console::open(simple_text_output_interface);
 
         console.clear_screen()?;
         console.set_cursor_pos(0, 0)?;
@@ -40,37 +184,178 @@ impl Console {
         Ok(console)
     }
 
+    fn open(simple_text_output_interface: *const efi::SIMPLE_TEXT_OUTPUT_PROTOCOL) -> Self {
+        Console(
+            crate::from_pointer(simple_text_output_interface),
+            simple_text_output_interface,
+            false,
+        )
+    }
+
+    pub fn reset(&self, extended_verification: bool) -> Result<(), crate::Error> {
+        let status = unsafe {
+            (self.0.reset)(
+                self.1,
+                if extended_verification {
+                    efi::TRUE
+                } else {
+                    efi::FALSE
+                },
+            )
+        };
+        status.to_result("Failed to reset console")
+    }
+
+    pub fn test_string(&self, string: &CStr16) -> Result<bool, crate::Error> {
+        let status = unsafe { (self.0.test_string)(self.1, string.as_ptr()) };
+        if status == efi::STATUS::UNSUPPORTED {
+            return Ok(false);
+        }
+        status.to_result("Failed to test string")?;
+        Ok(true)
+    }
+
+    pub fn query_mode(&self, mode: usize) -> Result<(usize, usize), crate::Error> {
+        let mut columns = 0;
+        let mut rows = 0;
+        let status = unsafe { (self.0.query_mode)(self.1, mode, &mut columns, &mut rows) };
+        status.to_result("Failed to query console mode")?;
+        Ok((columns, rows))
+    }
+
+    pub fn set_mode(&self, mode: usize) -> Result<(), crate::Error> {
+        let status = unsafe { (self.0.set_mode)(self.1, mode) };
+        status.to_result("Failed to set console mode")
+    }
+
+    pub fn set_largest_mode(&self) -> Result<(), crate::Error> {
+        let mut largest = None;
+        let mut largest_size = 0;
+        for mode in 0..self.mode().max_mode {
+            // Not every mode below the maximum has to be supported
+            if let Ok((columns, rows)) = self.query_mode(mode) {
+                if columns * rows > largest_size {
+                    largest = Some(mode);
+                    largest_size = columns * rows;
+                }
+            }
+        }
+
+        match largest {
+            Some(mode) if mode != self.mode().mode => self.set_mode(mode),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn set_attribute(&self, foreground: Color, background: Color) -> Result<(), crate::Error> {
+        // Only the first eight colors can be used as a background
+        if background as usize > efi::LIGHTGRAY {
+            return Err(crate::Error::new(
+                efi::STATUS::INVALID_PARAMETER,
+                "Invalid background color",
+            ));
+        }
+
+        let status = unsafe {
+            (self.0.set_attribute)(
+                self.1,
+                efi::TEXT_ATTR(foreground as usize, background as usize),
+            )
+        };
+        status.to_result("Failed to set console attribute")
+    }
+
+    pub fn enable_cursor(&self, visible: bool) -> Result<(), crate::Error> {
+        let status =
+            unsafe { (self.0.enable_cursor)(self.1, if visible { efi::TRUE } else { efi::FALSE }) };
+        status.to_result("Failed to set cursor visibility")
+    }
+
+    pub fn mode(&self) -> ConsoleMode {
+        let mode = unsafe { &*self.0.mode };
+        ConsoleMode {
+            max_mode: mode.max_mode.max(0) as usize,
+            mode: mode.mode.max(0) as usize,
+            foreground: (mode.attribute & 0x0F) as usize,
+            background: ((mode.attribute >> 4) & 0x07) as usize,
+            cursor_column: mode.cursor_column.max(0) as usize,
+            cursor_row: mode.cursor_row.max(0) as usize,
+            cursor_visible: mode.cursor_visible != efi::FALSE,
+        }
+    }
+
+    pub fn size(&self) -> Result<(usize, usize), crate::Error> {
+        self.query_mode(self.mode().mode)
+    }
+
     pub fn clear_screen(&self) -> Result<(), crate::Error> {
         let status = unsafe { (self.0.clear_screen)(self.1) };
-        match status {
-            efi::STATUS::SUCCESS => Ok(()),
-            _ => Err(crate::Error::new(status, "Failed to clear standard output")),
-        }
+        status.to_result("Failed to clear standard output")
     }
 
     pub fn set_cursor_pos(&self, column: usize, row: usize) -> Result<(), crate::Error> {
         let status = unsafe { (self.0.set_cursor_pos)(self.1, column, row) };
-        match status {
-            efi::STATUS::SUCCESS => Ok(()),
-            _ => Err(crate::Error::new(status, "Failed to clear standard error")),
+        status.to_result("Failed to clear standard error")
+    }
+
+    fn output_string(&self, buffer: &[efi::CHAR16]) -> fmt::Result {
+        // Warnings such as an unknown glyph still print the rest of the string
+        let status = unsafe { (self.0.output_string)(self.1, buffer.as_ptr()) };
+        if status.is_error() {
+            Err(fmt::Error {})
+        } else {
+            Ok(())
         }
     }
 }
 
 impl fmt::Write for Console {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        let mut str: Vec<efi::CHAR16> = Vec::with_capacity(s.len() + 1);
+        // Convert through a stack buffer so printing never needs the allocator
+        let mut buffer = [0; OUTPUT_BUFFER_SIZE];
+        let mut length = 0;
 
         for c in s.chars() {
-            str.push(c as efi::CHAR16);
-        }
+            // Leave room for a carriage return, the character and the null
+            if length + 3 > OUTPUT_BUFFER_SIZE {
+                buffer[length] = 0;
+                self.output_string(&buffer[..=length])?;
+                length = 0;
+            }
+
+            // The firmware needs both a carriage return and a line feed to start a new line
+            if c == '\n' && !self.2 {
+                buffer[length] = '\r' as efi::CHAR16;
+                length += 1;
+            }
+            self.2 = c == '\r';
 
-        str.push(0);
+            match string::encode_ucs2(c, unsafe { UCS2_POLICY }) {
+                Ok(Some(c)) => {
+                    buffer[length] = c;
+                    length += 1;
+                }
+                Ok(None) => {}
+                Err(_) => return Err(fmt::Error {}),
+            }
+        }
 
-        match unsafe { (self.0.output_string)(self.1, str.as_ptr()) } {
-            efi::STATUS::SUCCESS => Ok(()),
-            _ => Err(fmt::Error {}),
+        if length > 0 {
+            buffer[length] = 0;
+            self.output_string(&buffer[..=length])?;
         }
+
+        Ok(())
+    }
+}
+
+// Output past the end of the log is dropped
+impl fmt::Write for BootLog {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let length = s.len().min(BOOT_LOG_SIZE - self.length);
+        self.buffer[self.length..self.length + length].copy_from_slice(&s.as_bytes()[..length]);
+        self.length += length;
+        Ok(())
     }
 }
 
@@ -81,6 +366,17 @@ macro_rules! print {
 
 #[macro_export]
 macro_rules! println {
-    () => ($crate::print!("\r\n"));
-    ($($arg:tt)*) => ($crate::print!("{}\r\n", format_args!($($arg)*)));
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! eprint {
+    ($($arg:tt)*) => ($crate::console::_eprint(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! eprintln {
+    () => ($crate::eprint!("\n"));
+    ($($arg:tt)*) => ($crate::eprint!("{}\n", format_args!($($arg)*)));
 }
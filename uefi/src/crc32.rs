@@ -0,0 +1,37 @@
+// The CRC32 used by UEFI tables and GPT headers (IEEE 802.3, reflected)
+const POLYNOMIAL: u32 = 0xEDB88320;
+
+const TABLE: [u32; 256] = make_table();
+
+pub fn crc32(data: &[u8]) -> u32 {
+    !update(!0, data)
+}
+
+// Continues a running CRC, which starts at !0 and is inverted once finished
+pub fn update(crc: u32, data: &[u8]) -> u32 {
+    data.iter().fold(crc, |crc, byte| {
+        TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+
+        table[i] = crc;
+        i += 1;
+    }
+
+    table
+}
@@ -0,0 +1,961 @@
+use crate::{config_table::GUID, efi, ResultExt};
+use alloc::{string::String, vec::Vec};
+use core::{fmt, str::FromStr};
+
+const HEADER_SIZE: usize = 4;
+const END_NODE: [u8; HEADER_SIZE] = [
+    efi::END_DEVICE_PATH_TYPE,
+    efi::END_ENTIRE_DEVICE_PATH_SUBTYPE,
+    HEADER_SIZE as u8,
+    0,
+];
+
+// Compressed EISA IDs for "PNP0A03" and "PNP0A08", the PCI and PCIe root bridges
+const EISA_PNP_ID: u32 = 0x41D0;
+const PCI_ROOT_HID: u32 = 0x0A03_41D0;
+const PCIE_ROOT_HID: u32 = 0x0A08_41D0;
+
+// A copy of a firmware device path, always ending with an end entire node
+#[derive(Clone, PartialEq, Eq)]
+pub struct DevicePath {
+    data: Vec<u8>,
+}
+
+#[derive(Clone, Copy)]
+pub struct RawNode<'a> {
+    pub node_type: u8,
+    pub sub_type: u8,
+    pub data: &'a [u8],
+}
+
+pub enum Node<'a> {
+    Pci {
+        device: u8,
+        function: u8,
+    },
+    MemoryMapped {
+        memory_type: u32,
+        start: u64,
+        end: u64,
+    },
+    VendorHardware {
+        guid: GUID,
+        data: &'a [u8],
+    },
+    Controller(u32),
+    Acpi {
+        hid: u32,
+        uid: u32,
+    },
+    AcpiAdr(u32),
+    Atapi {
+        secondary: bool,
+        slave: bool,
+        lun: u16,
+    },
+    Scsi {
+        target: u16,
+        lun: u16,
+    },
+    Usb {
+        parent_port: u8,
+        interface: u8,
+    },
+    VendorMessaging {
+        guid: GUID,
+        data: &'a [u8],
+    },
+    MacAddress {
+        address: [u8; 32],
+        interface_type: u8,
+    },
+    Sata {
+        hba_port: u16,
+        port_multiplier_port: u16,
+        lun: u16,
+    },
+    Nvme {
+        namespace_id: u32,
+        eui64: [u8; 8],
+    },
+    HardDrive {
+        partition_number: u32,
+        start: u64,
+        size: u64,
+        signature: PartitionSignature,
+    },
+    CdRom {
+        boot_entry: u32,
+        start: u64,
+        size: u64,
+    },
+    VendorMedia {
+        guid: GUID,
+        data: &'a [u8],
+    },
+    FilePath(String),
+    MediaProtocol(GUID),
+    FirmwareFile(GUID),
+    FirmwareVolume(GUID),
+    EndInstance,
+    Unknown(RawNode<'a>),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PartitionSignature {
+    None,
+    Mbr(u32),
+    Gpt(GUID),
+}
+
+pub struct Nodes<'a> {
+    data: &'a [u8],
+}
+
+impl DevicePath {
+    pub fn new() -> Self {
+        DevicePath {
+            data: END_NODE.to_vec(),
+        }
+    }
+
+    /// # Safety
+    /// `ptr` must point to a device path terminated by an end entire node
+    pub unsafe fn from_ptr(
+        ptr: *const efi::DEVICE_PATH_PROTOCOL,
+    ) -> Result<DevicePath, crate::Error> {
+        let start = ptr as *const u8;
+        let mut offset = 0;
+        loop {
+            let header = core::slice::from_raw_parts(start.add(offset), HEADER_SIZE);
+            let length = u16::from_le_bytes([header[2], header[3]]) as usize;
+            if length < HEADER_SIZE {
+                return Err(invalid_node());
+            }
+
+            offset += length;
+            if header[0] == efi::END_DEVICE_PATH_TYPE
+                && header[1] == efi::END_ENTIRE_DEVICE_PATH_SUBTYPE
+            {
+                break;
+            }
+        }
+
+        Ok(DevicePath {
+            data: core::slice::from_raw_parts(start, offset).to_vec(),
+        })
+    }
+
+    pub fn for_handle(handle: efi::HANDLE) -> Result<DevicePath, crate::Error> {
+        let ptr = crate::handle_protocol(handle, &efi::DEVICE_PATH_PROTOCOL_GUID)
+            .context("Failed to get device path")?;
+        unsafe { DevicePath::from_ptr(ptr) }
+    }
+
+    pub fn as_ptr(&self) -> *const efi::DEVICE_PATH_PROTOCOL {
+        self.data.as_ptr() as *const efi::DEVICE_PATH_PROTOCOL
+    }
+
+    // The size in bytes, including the end node
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn nodes(&self) -> Nodes<'_> {
+        Nodes {
+            data: &self.data[..self.data.len() - HEADER_SIZE],
+        }
+    }
+
+    // Whether `prefix` names this device or one of its parents
+    pub fn starts_with(&self, prefix: &DevicePath) -> bool {
+        let prefix = &prefix.data[..prefix.size() - HEADER_SIZE];
+        self.data[..self.size() - HEADER_SIZE].starts_with(prefix)
+    }
+
+    pub fn push(&mut self, node_type: u8, sub_type: u8, data: &[u8]) -> Result<(), crate::Error> {
+        let length = HEADER_SIZE + data.len();
+        if length > u16::MAX as usize {
+            return Err(crate::Error::new(
+                efi::STATUS::BAD_BUFFER_SIZE,
+                "Device path node is too large",
+            ));
+        }
+
+        let end = self.data.len() - HEADER_SIZE;
+        let mut node = Vec::with_capacity(length);
+        node.extend_from_slice(&[node_type, sub_type]);
+        node.extend_from_slice(&(length as u16).to_le_bytes());
+        node.extend_from_slice(data);
+        self.data.splice(end..end, node);
+
+        Ok(())
+    }
+
+    pub fn append(&mut self, other: &DevicePath) {
+        let end = self.data.len() - HEADER_SIZE;
+        self.data.splice(
+            end..end,
+            other.data[..other.size() - HEADER_SIZE].iter().cloned(),
+        );
+    }
+
+    pub fn append_file_path(&mut self, path: &str) -> Result<(), crate::Error> {
+        let mut data = Vec::with_capacity((path.len() + 1) * 2);
+        for code in path.encode_utf16().chain(core::iter::once(0)) {
+            data.extend_from_slice(&code.to_le_bytes());
+        }
+
+        self.push(efi::MEDIA_DEVICE_PATH, efi::MEDIA_FILEPATH_DP, &data)
+    }
+}
+
+impl Default for DevicePath {
+    fn default() -> Self {
+        DevicePath::new()
+    }
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = RawNode<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.len() < HEADER_SIZE {
+            return None;
+        }
+
+        let length = (read_u16(self.data, 2) as usize).clamp(HEADER_SIZE, self.data.len());
+        let node = RawNode {
+            node_type: self.data[0],
+            sub_type: self.data[1],
+            data: &self.data[HEADER_SIZE..length],
+        };
+        self.data = &self.data[length..];
+
+        Some(node)
+    }
+}
+
+impl<'a> RawNode<'a> {
+    // Nodes that are too short for their type are left as unknown
+    pub fn parse(self) -> Node<'a> {
+        let data = self.data;
+        let len = data.len();
+
+        match (self.node_type, self.sub_type) {
+            (efi::HARDWARE_DEVICE_PATH, efi::HW_PCI_DP) if len >= 2 => Node::Pci {
+                function: data[0],
+                device: data[1],
+            },
+            (efi::HARDWARE_DEVICE_PATH, efi::HW_MEMMAP_DP) if len >= 20 => Node::MemoryMapped {
+                memory_type: read_u32(data, 0),
+                start: read_u64(data, 4),
+                end: read_u64(data, 12),
+            },
+            (efi::HARDWARE_DEVICE_PATH, efi::HW_VENDOR_DP) if len >= 16 => Node::VendorHardware {
+                guid: read_guid(data, 0),
+                data: &data[16..],
+            },
+            (efi::HARDWARE_DEVICE_PATH, efi::HW_CONTROLLER_DP) if len >= 4 => {
+                Node::Controller(read_u32(data, 0))
+            }
+            (efi::ACPI_DEVICE_PATH, efi::ACPI_DP) if len >= 8 => Node::Acpi {
+                hid: read_u32(data, 0),
+                uid: read_u32(data, 4),
+            },
+            (efi::ACPI_DEVICE_PATH, efi::ACPI_ADR_DP) if len >= 4 => {
+                Node::AcpiAdr(read_u32(data, 0))
+            }
+            (efi::MESSAGING_DEVICE_PATH, efi::MSG_ATAPI_DP) if len >= 4 => Node::Atapi {
+                secondary: data[0] != 0,
+                slave: data[1] != 0,
+                lun: read_u16(data, 2),
+            },
+            (efi::MESSAGING_DEVICE_PATH, efi::MSG_SCSI_DP) if len >= 4 => Node::Scsi {
+                target: read_u16(data, 0),
+                lun: read_u16(data, 2),
+            },
+            (efi::MESSAGING_DEVICE_PATH, efi::MSG_USB_DP) if len >= 2 => Node::Usb {
+                parent_port: data[0],
+                interface: data[1],
+            },
+            (efi::MESSAGING_DEVICE_PATH, efi::MSG_VENDOR_DP) if len >= 16 => {
+                Node::VendorMessaging {
+                    guid: read_guid(data, 0),
+                    data: &data[16..],
+                }
+            }
+            (efi::MESSAGING_DEVICE_PATH, efi::MSG_MAC_ADDR_DP) if len >= 33 => {
+                let mut address = [0; 32];
+                address.copy_from_slice(&data[..32]);
+                Node::MacAddress {
+                    address,
+                    interface_type: data[32],
+                }
+            }
+            (efi::MESSAGING_DEVICE_PATH, efi::MSG_SATA_DP) if len >= 6 => Node::Sata {
+                hba_port: read_u16(data, 0),
+                port_multiplier_port: read_u16(data, 2),
+                lun: read_u16(data, 4),
+            },
+            (efi::MESSAGING_DEVICE_PATH, efi::MSG_NVME_NAMESPACE_DP) if len >= 12 => {
+                let mut eui64 = [0; 8];
+                eui64.copy_from_slice(&data[4..12]);
+                Node::Nvme {
+                    namespace_id: read_u32(data, 0),
+                    eui64,
+                }
+            }
+            (efi::MEDIA_DEVICE_PATH, efi::MEDIA_HARDDRIVE_DP) if len >= 38 => Node::HardDrive {
+                partition_number: read_u32(data, 0),
+                start: read_u64(data, 4),
+                size: read_u64(data, 12),
+                signature: match data[37] {
+                    efi::SIGNATURE_TYPE_MBR => PartitionSignature::Mbr(read_u32(data, 20)),
+                    efi::SIGNATURE_TYPE_GUID => PartitionSignature::Gpt(read_guid(data, 20)),
+                    _ => PartitionSignature::None,
+                },
+            },
+            (efi::MEDIA_DEVICE_PATH, efi::MEDIA_CDROM_DP) if len >= 20 => Node::CdRom {
+                boot_entry: read_u32(data, 0),
+                start: read_u64(data, 4),
+                size: read_u64(data, 12),
+            },
+            (efi::MEDIA_DEVICE_PATH, efi::MEDIA_VENDOR_DP) if len >= 16 => Node::VendorMedia {
+                guid: read_guid(data, 0),
+                data: &data[16..],
+            },
+            (efi::MEDIA_DEVICE_PATH, efi::MEDIA_FILEPATH_DP) => {
+                let codes = data
+                    .chunks_exact(2)
+                    .map(|code| u16::from_le_bytes([code[0], code[1]]))
+                    .take_while(|code| *code != 0);
+                Node::FilePath(
+                    core::char::decode_utf16(codes)
+                        .map(|c| c.unwrap_or(core::char::REPLACEMENT_CHARACTER))
+                        .collect(),
+                )
+            }
+            (efi::MEDIA_DEVICE_PATH, efi::MEDIA_PROTOCOL_DP) if len >= 16 => {
+                Node::MediaProtocol(read_guid(data, 0))
+            }
+            (efi::MEDIA_DEVICE_PATH, efi::MEDIA_PIWG_FW_FILE_DP) if len >= 16 => {
+                Node::FirmwareFile(read_guid(data, 0))
+            }
+            (efi::MEDIA_DEVICE_PATH, efi::MEDIA_PIWG_FW_VOL_DP) if len >= 16 => {
+                Node::FirmwareVolume(read_guid(data, 0))
+            }
+            (efi::END_DEVICE_PATH_TYPE, efi::END_INSTANCE_DEVICE_PATH_SUBTYPE) => Node::EndInstance,
+            _ => Node::Unknown(self),
+        }
+    }
+}
+
+// Uses the text form from section 10.6.1.6 of the specification
+impl fmt::Display for DevicePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut separator = "";
+        for node in self.nodes() {
+            let node = node.parse();
+            if let Node::EndInstance = node {
+                separator = "";
+                f.write_str(",")?;
+                continue;
+            }
+
+            write!(f, "{}{}", separator, node)?;
+            separator = "/";
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for DevicePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl fmt::Display for Node<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Pci { device, function } => write!(f, "Pci({:#X},{:#X})", device, function),
+            Node::MemoryMapped {
+                memory_type,
+                start,
+                end,
+            } => write!(
+                f,
+                "MemoryMapped({:#X},{:#X},{:#X})",
+                memory_type, start, end
+            ),
+            Node::VendorHardware { guid, data } => write_vendor(f, "VenHw", guid, data),
+            Node::Controller(controller) => write!(f, "Ctrl({:#X})", controller),
+            Node::Acpi { hid, uid } => match *hid {
+                PCI_ROOT_HID => write!(f, "PciRoot({:#X})", uid),
+                PCIE_ROOT_HID => write!(f, "PcieRoot({:#X})", uid),
+                hid if hid & 0xFFFF == EISA_PNP_ID => {
+                    write!(f, "Acpi(PNP{:04X},{:#X})", hid >> 16, uid)
+                }
+                hid => write!(f, "Acpi({:#X},{:#X})", hid, uid),
+            },
+            Node::AcpiAdr(adr) => write!(f, "AcpiAdr({:#X})", adr),
+            Node::Atapi {
+                secondary,
+                slave,
+                lun,
+            } => write!(
+                f,
+                "Ata({},{},{:#X})",
+                if *secondary { "Secondary" } else { "Primary" },
+                if *slave { "Slave" } else { "Master" },
+                lun
+            ),
+            Node::Scsi { target, lun } => write!(f, "Scsi({:#X},{:#X})", target, lun),
+            Node::Usb {
+                parent_port,
+                interface,
+            } => write!(f, "USB({:#X},{:#X})", parent_port, interface),
+            Node::VendorMessaging { guid, data } => write_vendor(f, "VenMsg", guid, data),
+            Node::MacAddress {
+                address,
+                interface_type,
+            } => {
+                // Ethernet addresses are 6 bytes, the rest is padding
+                let length = if *interface_type <= 1 { 6 } else { 32 };
+                f.write_str("MAC(")?;
+                write_hex(f, &address[..length])?;
+                write!(f, ",{:#X})", interface_type)
+            }
+            Node::Sata {
+                hba_port,
+                port_multiplier_port,
+                lun,
+            } => write!(
+                f,
+                "Sata({:#X},{:#X},{:#X})",
+                hba_port, port_multiplier_port, lun
+            ),
+            Node::Nvme {
+                namespace_id,
+                eui64,
+            } => {
+                write!(f, "NVMe({:#X},", namespace_id)?;
+                for (i, byte) in eui64.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { "-" };
+                    write!(f, "{}{:02X}", separator, byte)?;
+                }
+                f.write_str(")")
+            }
+            Node::HardDrive {
+                partition_number,
+                start,
+                size,
+                signature,
+            } => {
+                write!(f, "HD({},", partition_number)?;
+                match signature {
+                    PartitionSignature::None => f.write_str("0,0")?,
+                    PartitionSignature::Mbr(signature) => write!(f, "MBR,{:#010X}", signature)?,
+                    PartitionSignature::Gpt(guid) => write!(f, "GPT,{}", guid)?,
+                }
+                write!(f, ",{:#X},{:#X})", start, size)
+            }
+            Node::CdRom {
+                boot_entry,
+                start,
+                size,
+            } => write!(f, "CDROM({:#X},{:#X},{:#X})", boot_entry, start, size),
+            Node::VendorMedia { guid, data } => write_vendor(f, "VenMedia", guid, data),
+            Node::FilePath(path) => f.write_str(path),
+            Node::MediaProtocol(guid) => write!(f, "Media({})", guid),
+            Node::FirmwareFile(guid) => write!(f, "FvFile({})", guid),
+            Node::FirmwareVolume(guid) => write!(f, "Fv({})", guid),
+            Node::EndInstance => Ok(()),
+            Node::Unknown(node) => {
+                write!(f, "Path({},{},", node.node_type, node.sub_type)?;
+                write_hex(f, node.data)?;
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+impl FromStr for DevicePath {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut path = DevicePath::new();
+
+        // Split on the separators that aren't inside a node's arguments
+        let mut depth = 0;
+        let mut start = 0;
+        for (i, c) in s.char_indices().chain(core::iter::once((s.len(), '\0'))) {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                // A file path can contain commas, so only one followed by a
+                // node starts another instance
+                ',' if depth == 0 && !is_node(&s[start..i]) && !is_node_start(&s[i + 1..]) => {}
+                '/' | ',' | '\0' if depth == 0 => {
+                    let node = &s[start..i];
+                    if !node.is_empty() {
+                        parse_node(&mut path, node)
+                            .with_context(format_args!("Invalid device path node \"{}\"", node))?;
+                    }
+                    if c == ',' {
+                        path.push(
+                            efi::END_DEVICE_PATH_TYPE,
+                            efi::END_INSTANCE_DEVICE_PATH_SUBTYPE,
+                            &[],
+                        )?;
+                    }
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(path)
+    }
+}
+
+// Loaded images only hold the path relative to their device, so the two are joined
+pub fn loaded_image() -> Result<DevicePath, crate::Error> {
+    let loaded_image: *const efi::LOADED_IMAGE_PROTOCOL = crate::handle_protocol(
+        unsafe { crate::IMAGE_HANDLE },
+        &efi::LOADED_IMAGE_PROTOCOL_GUID,
+    )?;
+
+    let (device, file_path) = unsafe { ((*loaded_image).device_handle, (*loaded_image).file_path) };
+    let mut path = DevicePath::for_handle(device)?;
+    if !file_path.is_null() {
+        path.append(&unsafe { DevicePath::from_ptr(file_path) }?);
+    }
+
+    Ok(path)
+}
+
+fn parse_node(path: &mut DevicePath, node: &str) -> Result<(), crate::Error> {
+    // Anything that isn't "Name(arguments)" is a file path
+    let (name, arguments) = match node.strip_suffix(')').and_then(|n| n.split_once('(')) {
+        Some((name, arguments)) => (name, arguments),
+        None => return path.append_file_path(node),
+    };
+    let arguments: Vec<&str> = arguments.split(',').map(str::trim).collect();
+    let arg = |i: usize| arguments.get(i).copied().unwrap_or("");
+    let num = |i: usize| parse_number(arg(i));
+
+    let mut data = Vec::new();
+    let (node_type, sub_type) = match name {
+        "Pci" => {
+            data.extend_from_slice(&[num(1)? as u8, num(0)? as u8]);
+            (efi::HARDWARE_DEVICE_PATH, efi::HW_PCI_DP)
+        }
+        "MemoryMapped" => {
+            data.extend_from_slice(&(num(0)? as u32).to_le_bytes());
+            data.extend_from_slice(&num(1)?.to_le_bytes());
+            data.extend_from_slice(&num(2)?.to_le_bytes());
+            (efi::HARDWARE_DEVICE_PATH, efi::HW_MEMMAP_DP)
+        }
+        "VenHw" | "VenMsg" | "VenMedia" => {
+            data.extend_from_slice(&guid_bytes(&arg(0).parse()?));
+            if arguments.len() > 1 {
+                parse_hex(arg(1), &mut data)?;
+            }
+            match name {
+                "VenHw" => (efi::HARDWARE_DEVICE_PATH, efi::HW_VENDOR_DP),
+                "VenMsg" => (efi::MESSAGING_DEVICE_PATH, efi::MSG_VENDOR_DP),
+                _ => (efi::MEDIA_DEVICE_PATH, efi::MEDIA_VENDOR_DP),
+            }
+        }
+        "Ctrl" => {
+            data.extend_from_slice(&(num(0)? as u32).to_le_bytes());
+            (efi::HARDWARE_DEVICE_PATH, efi::HW_CONTROLLER_DP)
+        }
+        "PciRoot" | "PcieRoot" | "Acpi" => {
+            let (hid, uid) = match name {
+                "PciRoot" => (PCI_ROOT_HID, num(0)?),
+                "PcieRoot" => (PCIE_ROOT_HID, num(0)?),
+                _ => match arg(0).strip_prefix("PNP") {
+                    Some(id) => ((parse_hex_u32(id)? << 16) | EISA_PNP_ID, num(1)?),
+                    None => (num(0)? as u32, num(1)?),
+                },
+            };
+            data.extend_from_slice(&hid.to_le_bytes());
+            data.extend_from_slice(&(uid as u32).to_le_bytes());
+            (efi::ACPI_DEVICE_PATH, efi::ACPI_DP)
+        }
+        "AcpiAdr" => {
+            data.extend_from_slice(&(num(0)? as u32).to_le_bytes());
+            (efi::ACPI_DEVICE_PATH, efi::ACPI_ADR_DP)
+        }
+        "Ata" => {
+            let secondary = match arg(0) {
+                "Primary" => 0,
+                "Secondary" => 1,
+                _ => num(0)? as u8,
+            };
+            let slave = match arg(1) {
+                "Master" => 0,
+                "Slave" => 1,
+                _ => num(1)? as u8,
+            };
+            data.extend_from_slice(&[secondary, slave]);
+            data.extend_from_slice(&(num(2)? as u16).to_le_bytes());
+            (efi::MESSAGING_DEVICE_PATH, efi::MSG_ATAPI_DP)
+        }
+        "Scsi" => {
+            data.extend_from_slice(&(num(0)? as u16).to_le_bytes());
+            data.extend_from_slice(&(num(1)? as u16).to_le_bytes());
+            (efi::MESSAGING_DEVICE_PATH, efi::MSG_SCSI_DP)
+        }
+        "USB" => {
+            data.extend_from_slice(&[num(0)? as u8, num(1)? as u8]);
+            (efi::MESSAGING_DEVICE_PATH, efi::MSG_USB_DP)
+        }
+        "MAC" => {
+            parse_hex(arg(0), &mut data)?;
+            if data.len() > 32 {
+                return Err(invalid_node());
+            }
+            data.resize(32, 0);
+            data.push(num(1)? as u8);
+            (efi::MESSAGING_DEVICE_PATH, efi::MSG_MAC_ADDR_DP)
+        }
+        "Sata" => {
+            for i in 0..3 {
+                data.extend_from_slice(&(num(i)? as u16).to_le_bytes());
+            }
+            (efi::MESSAGING_DEVICE_PATH, efi::MSG_SATA_DP)
+        }
+        "NVMe" => {
+            data.extend_from_slice(&(num(0)? as u32).to_le_bytes());
+            parse_hex(&arg(1).replace('-', ""), &mut data)?;
+            if data.len() != 12 {
+                return Err(invalid_node());
+            }
+            (efi::MESSAGING_DEVICE_PATH, efi::MSG_NVME_NAMESPACE_DP)
+        }
+        "HD" => {
+            let mut signature = [0; 16];
+            let (mbr_type, signature_type) = match arg(1) {
+                "MBR" => {
+                    signature[..4].copy_from_slice(&(num(2)? as u32).to_le_bytes());
+                    (efi::MBR_TYPE_PCAT, efi::SIGNATURE_TYPE_MBR)
+                }
+                "GPT" => {
+                    signature = guid_bytes(&arg(2).parse()?);
+                    (
+                        efi::MBR_TYPE_EFI_PARTITION_TABLE_HEADER,
+                        efi::SIGNATURE_TYPE_GUID,
+                    )
+                }
+                _ => (efi::MBR_TYPE_PCAT, efi::NO_DISK_SIGNATURE),
+            };
+            data.extend_from_slice(&(num(0)? as u32).to_le_bytes());
+            data.extend_from_slice(&num(3)?.to_le_bytes());
+            data.extend_from_slice(&num(4)?.to_le_bytes());
+            data.extend_from_slice(&signature);
+            data.extend_from_slice(&[mbr_type, signature_type]);
+            (efi::MEDIA_DEVICE_PATH, efi::MEDIA_HARDDRIVE_DP)
+        }
+        "CDROM" => {
+            data.extend_from_slice(&(num(0)? as u32).to_le_bytes());
+            data.extend_from_slice(&num(1)?.to_le_bytes());
+            data.extend_from_slice(&num(2)?.to_le_bytes());
+            (efi::MEDIA_DEVICE_PATH, efi::MEDIA_CDROM_DP)
+        }
+        "Media" | "FvFile" | "Fv" => {
+            data.extend_from_slice(&guid_bytes(&arg(0).parse()?));
+            match name {
+                "Media" => (efi::MEDIA_DEVICE_PATH, efi::MEDIA_PROTOCOL_DP),
+                "FvFile" => (efi::MEDIA_DEVICE_PATH, efi::MEDIA_PIWG_FW_FILE_DP),
+                _ => (efi::MEDIA_DEVICE_PATH, efi::MEDIA_PIWG_FW_VOL_DP),
+            }
+        }
+        "Path" => {
+            parse_hex(arg(2), &mut data)?;
+            (num(0)? as u8, num(1)? as u8)
+        }
+        _ => {
+            return Err(crate::Error::new(
+                efi::STATUS::UNSUPPORTED,
+                "Unknown device path node",
+            ))
+        }
+    };
+
+    path.push(node_type, sub_type, &data)
+}
+
+// Whether `s` is "Name(arguments)" rather than a file path
+fn is_node(s: &str) -> bool {
+    s.ends_with(')') && is_node_start(s)
+}
+
+fn is_node_start(s: &str) -> bool {
+    let name_length = s
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(s.len());
+    name_length > 0 && s[name_length..].starts_with('(')
+}
+
+fn parse_number(s: &str) -> Result<u64, crate::Error> {
+    let result = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    result.map_err(|_| invalid_node())
+}
+
+fn parse_hex_u32(s: &str) -> Result<u32, crate::Error> {
+    u32::from_str_radix(s, 16).map_err(|_| invalid_node())
+}
+
+fn parse_hex(s: &str, data: &mut Vec<u8>) -> Result<(), crate::Error> {
+    if s.len() % 2 != 0 {
+        return Err(invalid_node());
+    }
+
+    for i in (0..s.len()).step_by(2) {
+        let byte = s.get(i..i + 2).ok_or_else(invalid_node)?;
+        data.push(u8::from_str_radix(byte, 16).map_err(|_| invalid_node())?);
+    }
+
+    Ok(())
+}
+
+fn write_hex(f: &mut fmt::Formatter<'_>, data: &[u8]) -> fmt::Result {
+    for byte in data {
+        write!(f, "{:02X}", byte)?;
+    }
+
+    Ok(())
+}
+
+fn write_vendor(f: &mut fmt::Formatter<'_>, name: &str, guid: &GUID, data: &[u8]) -> fmt::Result {
+    write!(f, "{}({}", name, guid)?;
+    if !data.is_empty() {
+        f.write_str(",")?;
+        write_hex(f, data)?;
+    }
+    f.write_str(")")
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    read_u32(data, offset) as u64 | (read_u32(data, offset + 4) as u64) << 32
+}
+
+fn read_guid(data: &[u8], offset: usize) -> GUID {
+    let mut d = [0; 8];
+    d.copy_from_slice(&data[offset + 8..offset + 16]);
+
+    GUID {
+        a: read_u32(data, offset),
+        b: read_u16(data, offset + 4),
+        c: read_u16(data, offset + 6),
+        d,
+    }
+}
+
+fn guid_bytes(guid: &GUID) -> [u8; 16] {
+    let mut bytes = [0; 16];
+    bytes[..4].copy_from_slice(&guid.a.to_le_bytes());
+    bytes[4..6].copy_from_slice(&guid.b.to_le_bytes());
+    bytes[6..8].copy_from_slice(&guid.c.to_le_bytes());
+    bytes[8..].copy_from_slice(&guid.d);
+    bytes
+}
+
+fn invalid_node() -> crate::Error {
+    crate::Error::new(efi::STATUS::INVALID_PARAMETER, "Invalid device path node")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{format, string::ToString};
+
+    const PARTITION_GUID: &str = "4F68BCE3-E8CD-4DB1-A7CF-4BE2D1A5B2C4";
+
+    fn file_path(path: &str) -> Vec<u8> {
+        path.encode_utf16()
+            .chain(core::iter::once(0))
+            .flat_map(u16::to_le_bytes)
+            .collect()
+    }
+
+    // Checks both directions against the text form
+    fn round_trip(path: &DevicePath, text: &str) {
+        assert_eq!(path.to_string(), text);
+        assert_eq!(&text.parse::<DevicePath>().unwrap(), path);
+    }
+
+    fn boot_disk() -> DevicePath {
+        let mut path = DevicePath::new();
+        path.push(
+            efi::ACPI_DEVICE_PATH,
+            efi::ACPI_DP,
+            &[0xD0, 0x41, 0x03, 0x0A, 0, 0, 0, 0],
+        )
+        .unwrap();
+        path.push(efi::HARDWARE_DEVICE_PATH, efi::HW_PCI_DP, &[0x2, 0x1F])
+            .unwrap();
+        path.push(
+            efi::MESSAGING_DEVICE_PATH,
+            efi::MSG_SATA_DP,
+            &[0, 0, 0xFF, 0xFF, 0, 0],
+        )
+        .unwrap();
+        path
+    }
+
+    #[test]
+    fn round_trips_hard_drive_file_path() {
+        let mut path = boot_disk();
+        let mut hd = Vec::new();
+        hd.extend_from_slice(&1u32.to_le_bytes());
+        hd.extend_from_slice(&0x800u64.to_le_bytes());
+        hd.extend_from_slice(&0x10_0000u64.to_le_bytes());
+        hd.extend_from_slice(&guid_bytes(&PARTITION_GUID.parse().unwrap()));
+        hd.extend_from_slice(&[
+            efi::MBR_TYPE_EFI_PARTITION_TABLE_HEADER,
+            efi::SIGNATURE_TYPE_GUID,
+        ]);
+        path.push(efi::MEDIA_DEVICE_PATH, efi::MEDIA_HARDDRIVE_DP, &hd)
+            .unwrap();
+        path.append_file_path("\\EFI\\BOOT\\BOOTX64.EFI").unwrap();
+
+        round_trip(
+            &path,
+            &format!(
+                "PciRoot(0x0)/Pci(0x1F,0x2)/Sata(0x0,0xFFFF,0x0)/HD(1,GPT,{},0x800,0x100000)/\\EFI\\BOOT\\BOOTX64.EFI",
+                PARTITION_GUID
+            ),
+        );
+
+        let nodes: Vec<_> = path.nodes().map(RawNode::parse).collect();
+        assert!(matches!(
+            nodes[3],
+            Node::HardDrive {
+                partition_number: 1,
+                start: 0x800,
+                size: 0x10_0000,
+                signature: PartitionSignature::Gpt(_),
+            }
+        ));
+        assert!(matches!(&nodes[4], Node::FilePath(path) if path == "\\EFI\\BOOT\\BOOTX64.EFI"));
+    }
+
+    #[test]
+    fn round_trips_mbr_and_other_nodes() {
+        let mut path = DevicePath::new();
+        path.push(
+            efi::ACPI_DEVICE_PATH,
+            efi::ACPI_DP,
+            &[0xD0, 0x41, 0x01, 0x05, 1, 0, 0, 0],
+        )
+        .unwrap();
+        path.push(efi::MESSAGING_DEVICE_PATH, efi::MSG_USB_DP, &[0x3, 0x0])
+            .unwrap();
+        let mut hd = Vec::new();
+        hd.extend_from_slice(&2u32.to_le_bytes());
+        hd.extend_from_slice(&0x3Fu64.to_le_bytes());
+        hd.extend_from_slice(&0x1000u64.to_le_bytes());
+        hd.extend_from_slice(&0x1234_ABCDu32.to_le_bytes());
+        hd.extend_from_slice(&[0; 12]);
+        hd.extend_from_slice(&[efi::MBR_TYPE_PCAT, efi::SIGNATURE_TYPE_MBR]);
+        path.push(efi::MEDIA_DEVICE_PATH, efi::MEDIA_HARDDRIVE_DP, &hd)
+            .unwrap();
+        path.push(0x7F, 0x42, &[0xDE, 0xAD]).unwrap();
+
+        round_trip(
+            &path,
+            "Acpi(PNP0501,0x1)/USB(0x3,0x0)/HD(2,MBR,0x1234ABCD,0x3F,0x1000)/Path(127,66,DEAD)",
+        );
+    }
+
+    #[test]
+    fn round_trips_multiple_instances() {
+        let mut path = boot_disk();
+        path.push(
+            efi::END_DEVICE_PATH_TYPE,
+            efi::END_INSTANCE_DEVICE_PATH_SUBTYPE,
+            &[],
+        )
+        .unwrap();
+        path.append(&boot_disk());
+
+        let text = "PciRoot(0x0)/Pci(0x1F,0x2)/Sata(0x0,0xFFFF,0x0),PciRoot(0x0)/Pci(0x1F,0x2)/Sata(0x0,0xFFFF,0x0)";
+        round_trip(&path, text);
+        assert_eq!(
+            path.nodes()
+                .filter(|node| matches!(node.parse(), Node::EndInstance))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn keeps_commas_in_file_paths() {
+        let mut path = boot_disk();
+        path.append_file_path("\\EFI\\a,b.efi").unwrap();
+        round_trip(
+            &path,
+            "PciRoot(0x0)/Pci(0x1F,0x2)/Sata(0x0,0xFFFF,0x0)/\\EFI\\a,b.efi",
+        );
+
+        // Followed by a node, the comma still separates instances
+        let mut path = DevicePath::new();
+        path.append_file_path("\\a,b.efi").unwrap();
+        path.push(
+            efi::END_DEVICE_PATH_TYPE,
+            efi::END_INSTANCE_DEVICE_PATH_SUBTYPE,
+            &[],
+        )
+        .unwrap();
+        path.append(&boot_disk());
+        round_trip(
+            &path,
+            "\\a,b.efi,PciRoot(0x0)/Pci(0x1F,0x2)/Sata(0x0,0xFFFF,0x0)",
+        );
+        assert_eq!(file_path("\\a,b.efi"), path.nodes().next().unwrap().data);
+    }
+
+    #[test]
+    fn rejects_invalid_nodes() {
+        let err = "PciRoot(0x0)/Pci(0x1F,zz)"
+            .parse::<DevicePath>()
+            .err()
+            .unwrap();
+        assert_eq!(err.status(), efi::STATUS::INVALID_PARAMETER);
+
+        let err = "Bogus(0x0)".parse::<DevicePath>().err().unwrap();
+        assert_eq!(err.status(), efi::STATUS::UNSUPPORTED);
+    }
+
+    #[test]
+    fn checks_prefixes() {
+        let disk = boot_disk();
+        let mut partition = boot_disk();
+        partition.append_file_path("\\EFI").unwrap();
+        assert!(partition.starts_with(&disk));
+        assert!(!disk.starts_with(&partition));
+        assert_eq!(partition.size(), disk.size() + 4 + file_path("\\EFI").len());
+    }
+}
@@ -0,0 +1,292 @@
+pub const EDID_BLOCK_SIZE: usize = 128;
+
+const EDID_HEADER: [u8; 8] = [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00];
+
+const MANUFACTURER_ID_OFFSET: usize = 8;
+const PRODUCT_CODE_OFFSET: usize = 10;
+const SERIAL_NUMBER_OFFSET: usize = 12;
+const MANUFACTURE_WEEK_OFFSET: usize = 16;
+const MANUFACTURE_YEAR_OFFSET: usize = 17;
+const VERSION_OFFSET: usize = 18;
+const REVISION_OFFSET: usize = 19;
+const HORIZONTAL_SIZE_CM_OFFSET: usize = 21;
+const VERTICAL_SIZE_CM_OFFSET: usize = 22;
+const DETAILED_TIMING_OFFSET: usize = 54;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Edid {
+    pub present: bool,
+    pub raw: [u8; EDID_BLOCK_SIZE],
+    pub manufacturer_id: [u8; 3],
+    pub product_code: u16,
+    pub serial_number: u32,
+    pub manufacture_week: u8,
+    pub manufacture_year: u16,
+    pub version: u8,
+    pub revision: u8,
+    pub physical_width_mm: u32,
+    pub physical_height_mm: u32,
+    pub preferred_timing: DetailedTiming,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct DetailedTiming {
+    pub present: bool,
+    pub pixel_clock_khz: u32,
+    pub horizontal_active: u32,
+    pub horizontal_blanking: u32,
+    pub vertical_active: u32,
+    pub vertical_blanking: u32,
+    pub horizontal_size_mm: u32,
+    pub vertical_size_mm: u32,
+}
+
+impl Edid {
+    pub const fn empty() -> Self {
+        Edid {
+            present: false,
+            raw: [0; EDID_BLOCK_SIZE],
+            manufacturer_id: [0; 3],
+            product_code: 0,
+            serial_number: 0,
+            manufacture_week: 0,
+            manufacture_year: 0,
+            version: 0,
+            revision: 0,
+            physical_width_mm: 0,
+            physical_height_mm: 0,
+            preferred_timing: DetailedTiming::empty(),
+        }
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, crate::Error> {
+        if data.len() < EDID_BLOCK_SIZE {
+            return Err(crate::Error::new(
+                crate::Status::BAD_BUFFER_SIZE,
+                "EDID is smaller than the base block",
+            ));
+        }
+
+        let block = &data[..EDID_BLOCK_SIZE];
+
+        // Verify the header
+        if block[..EDID_HEADER.len()] != EDID_HEADER {
+            return Err(crate::Error::new(
+                crate::Status::COMPROMISED_DATA,
+                "Invalid EDID header",
+            ));
+        }
+
+        // Verify the checksum
+        if block.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(crate::Error::new(
+                crate::Status::CRC_ERROR,
+                "Invalid EDID checksum",
+            ));
+        }
+
+        let mut raw = [0; EDID_BLOCK_SIZE];
+        raw.copy_from_slice(block);
+
+        // Manufacturer ID is three 5-bit letters, big endian
+        let id = u16::from_be_bytes([
+            block[MANUFACTURER_ID_OFFSET],
+            block[MANUFACTURER_ID_OFFSET + 1],
+        ]);
+        let manufacturer_id = [
+            b'A' - 1 + ((id >> 10) & 0x1F) as u8,
+            b'A' - 1 + ((id >> 5) & 0x1F) as u8,
+            b'A' - 1 + (id & 0x1F) as u8,
+        ];
+
+        let preferred_timing = DetailedTiming::parse(
+            &block[DETAILED_TIMING_OFFSET..DETAILED_TIMING_OFFSET + DetailedTiming::SIZE],
+        );
+
+        // Prefer the millimetre size from the detailed timing over the centimetre size
+        let (physical_width_mm, physical_height_mm) =
+            if preferred_timing.horizontal_size_mm != 0 && preferred_timing.vertical_size_mm != 0 {
+                (
+                    preferred_timing.horizontal_size_mm,
+                    preferred_timing.vertical_size_mm,
+                )
+            } else {
+                (
+                    block[HORIZONTAL_SIZE_CM_OFFSET] as u32 * 10,
+                    block[VERTICAL_SIZE_CM_OFFSET] as u32 * 10,
+                )
+            };
+
+        Ok(Edid {
+            present: true,
+            raw,
+            manufacturer_id,
+            product_code: u16::from_le_bytes([
+                block[PRODUCT_CODE_OFFSET],
+                block[PRODUCT_CODE_OFFSET + 1],
+            ]),
+            serial_number: u32::from_le_bytes([
+                block[SERIAL_NUMBER_OFFSET],
+                block[SERIAL_NUMBER_OFFSET + 1],
+                block[SERIAL_NUMBER_OFFSET + 2],
+                block[SERIAL_NUMBER_OFFSET + 3],
+            ]),
+            manufacture_week: block[MANUFACTURE_WEEK_OFFSET],
+            manufacture_year: 1990 + block[MANUFACTURE_YEAR_OFFSET] as u16,
+            version: block[VERSION_OFFSET],
+            revision: block[REVISION_OFFSET],
+            physical_width_mm,
+            physical_height_mm,
+            preferred_timing,
+        })
+    }
+}
+
+impl DetailedTiming {
+    pub const SIZE: usize = 18;
+
+    pub const fn empty() -> Self {
+        DetailedTiming {
+            present: false,
+            pixel_clock_khz: 0,
+            horizontal_active: 0,
+            horizontal_blanking: 0,
+            vertical_active: 0,
+            vertical_blanking: 0,
+            horizontal_size_mm: 0,
+            vertical_size_mm: 0,
+        }
+    }
+
+    pub fn parse(descriptor: &[u8]) -> Self {
+        if descriptor.len() < Self::SIZE {
+            return Self::empty();
+        }
+
+        // A pixel clock of zero marks a display descriptor, not a timing
+        let pixel_clock = u16::from_le_bytes([descriptor[0], descriptor[1]]) as u32;
+        if pixel_clock == 0 {
+            return Self::empty();
+        }
+
+        let d = |i: usize| descriptor[i] as u32;
+
+        DetailedTiming {
+            present: true,
+            pixel_clock_khz: pixel_clock * 10,
+            horizontal_active: d(2) | ((d(4) & 0xF0) << 4),
+            horizontal_blanking: d(3) | ((d(4) & 0x0F) << 8),
+            vertical_active: d(5) | ((d(7) & 0xF0) << 4),
+            vertical_blanking: d(6) | ((d(7) & 0x0F) << 8),
+            horizontal_size_mm: d(12) | ((d(14) & 0xF0) << 4),
+            vertical_size_mm: d(13) | ((d(14) & 0x0F) << 8),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Base block of a 24" 1920x1080 monitor
+    const MONITOR: [u8; EDID_BLOCK_SIZE] = [
+        0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x10, 0xAC, 0xC4, 0xA0, 0x4C, 0x33, 0x32,
+        0x30, 0x1A, 0x1C, 0x01, 0x04, 0xA5, 0x35, 0x1E, 0x78, 0x3A, 0xEE, 0x95, 0xA3, 0x54, 0x4C,
+        0x99, 0x26, 0x0F, 0x50, 0x54, 0xA5, 0x4B, 0x00, 0x71, 0x4F, 0x81, 0x80, 0xA9, 0x40, 0xD1,
+        0xC0, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x02, 0x3A, 0x80, 0x18, 0x71, 0x38,
+        0x2D, 0x40, 0x58, 0x2C, 0x45, 0x00, 0x0F, 0x28, 0x21, 0x00, 0x00, 0x1E, 0x00, 0x00, 0x00,
+        0xFF, 0x00, 0x43, 0x46, 0x56, 0x39, 0x4E, 0x39, 0x39, 0x54, 0x30, 0x4C, 0x33, 0x4C, 0x0A,
+        0x00, 0x00, 0x00, 0xFC, 0x00, 0x44, 0x45, 0x4C, 0x4C, 0x20, 0x55, 0x32, 0x34, 0x31, 0x34,
+        0x48, 0x0A, 0x20, 0x00, 0x00, 0x00, 0xFD, 0x00, 0x38, 0x4C, 0x1E, 0x53, 0x11, 0x00, 0x0A,
+        0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x00, 0xC0,
+    ];
+
+    fn fix_checksum(block: &mut [u8; EDID_BLOCK_SIZE]) {
+        let sum = block[..EDID_BLOCK_SIZE - 1]
+            .iter()
+            .fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        block[EDID_BLOCK_SIZE - 1] = 0u8.wrapping_sub(sum);
+    }
+
+    #[test]
+    fn parses_base_block() {
+        let edid = Edid::parse(&MONITOR).unwrap();
+        assert!(edid.present);
+        assert_eq!(edid.raw, MONITOR);
+        assert_eq!(&edid.manufacturer_id, b"DEL");
+        assert_eq!(edid.product_code, 0xA0C4);
+        assert_eq!(edid.serial_number, 0x3032_334C);
+        assert_eq!(edid.manufacture_week, 26);
+        assert_eq!(edid.manufacture_year, 2018);
+        assert_eq!((edid.version, edid.revision), (1, 4));
+        assert_eq!(
+            (edid.physical_width_mm, edid.physical_height_mm),
+            (527, 296)
+        );
+    }
+
+    #[test]
+    fn parses_detailed_timing() {
+        let timing = DetailedTiming::parse(&MONITOR[DETAILED_TIMING_OFFSET..]);
+        assert!(timing.present);
+        assert_eq!(timing.pixel_clock_khz, 148_500);
+        assert_eq!(
+            (timing.horizontal_active, timing.horizontal_blanking),
+            (1920, 280)
+        );
+        assert_eq!(
+            (timing.vertical_active, timing.vertical_blanking),
+            (1080, 45)
+        );
+        assert_eq!(
+            (timing.horizontal_size_mm, timing.vertical_size_mm),
+            (527, 296)
+        );
+
+        // The serial number descriptor that follows isn't a timing
+        let descriptor = &MONITOR[DETAILED_TIMING_OFFSET + DetailedTiming::SIZE..];
+        assert!(!DetailedTiming::parse(descriptor).present);
+        assert!(!DetailedTiming::parse(&MONITOR[..DetailedTiming::SIZE - 1]).present);
+    }
+
+    #[test]
+    fn falls_back_to_centimetre_size() {
+        let mut block = MONITOR;
+        block[DETAILED_TIMING_OFFSET + 12..DETAILED_TIMING_OFFSET + 15].fill(0);
+        fix_checksum(&mut block);
+
+        let edid = Edid::parse(&block).unwrap();
+        assert!(edid.preferred_timing.present);
+        assert_eq!(
+            (edid.physical_width_mm, edid.physical_height_mm),
+            (530, 300)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_header() {
+        let mut block = MONITOR;
+        block[0] = 0xFF;
+        fix_checksum(&mut block);
+
+        let err = Edid::parse(&block).err().unwrap();
+        assert_eq!(err.status(), crate::Status::COMPROMISED_DATA);
+    }
+
+    #[test]
+    fn rejects_bad_checksum() {
+        let mut block = MONITOR;
+        block[EDID_BLOCK_SIZE - 1] ^= 1;
+
+        let err = Edid::parse(&block).err().unwrap();
+        assert_eq!(err.status(), crate::Status::CRC_ERROR);
+    }
+
+    #[test]
+    fn rejects_short_block() {
+        let err = Edid::parse(&MONITOR[..EDID_BLOCK_SIZE - 1]).err().unwrap();
+        assert_eq!(err.status(), crate::Status::BAD_BUFFER_SIZE);
+    }
+}
@@ -37,7 +37,7 @@ pub type IPv6_ADDRESS = [u8; 16];
 pub type IP_ADDRESS = [u8; 16];
 
 #[repr(C)]
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct GUID {
     pub a: u32,
     pub b: u16,
@@ -45,6 +45,64 @@ pub struct GUID {
     pub d: [u8; 8],
 }
 
+impl core::fmt::Display for GUID {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
+            self.a,
+            self.b,
+            self.c,
+            self.d[0],
+            self.d[1],
+            self.d[2],
+            self.d[3],
+            self.d[4],
+            self.d[5],
+            self.d[6],
+            self.d[7]
+        )
+    }
+}
+
+// Parses the registry format, "XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX"
+impl core::str::FromStr for GUID {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || crate::error!(STATUS::INVALID_PARAMETER, "Invalid GUID \"{}\"", s);
+
+        // Checked first so slicing below is always on a character boundary
+        let bytes = s.as_bytes();
+        if !s.is_ascii() || bytes.len() != 36 || [8, 13, 18, 23].iter().any(|i| bytes[*i] != b'-') {
+            return Err(invalid());
+        }
+
+        let hex = |start: usize, end: usize| {
+            let digits = &s[start..end];
+            if digits.bytes().all(|c| c.is_ascii_hexdigit()) {
+                u64::from_str_radix(digits, 16).map_err(|_| invalid())
+            } else {
+                Err(invalid())
+            }
+        };
+
+        let mut d = [0; 8];
+        d[0] = hex(19, 21)? as u8;
+        d[1] = hex(21, 23)? as u8;
+        for (i, byte) in d[2..].iter_mut().enumerate() {
+            *byte = hex(24 + i * 2, 26 + i * 2)? as u8;
+        }
+
+        Ok(GUID {
+            a: hex(0, 8)? as u32,
+            b: hex(9, 13)? as u16,
+            c: hex(14, 18)? as u16,
+            d,
+        })
+    }
+}
+
 /*
  * ================================================================
  * || 4.2 EFI Table Header
@@ -52,6 +110,7 @@ pub struct GUID {
  */
 
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub struct TABLE_HEADER {
     pub signature: UINT64,
     pub revision: UINT32,
@@ -67,13 +126,16 @@ pub struct TABLE_HEADER {
  */
 
 pub const SYSTEM_TABLE_SIGNATURE: UINT64 = 0x5453595320494249;
+pub const SYSTEM_TABLE_REVISION_1_10: UINT32 = (1 << 16) | 10;
+pub const SYSTEM_TABLE_REVISION_2_00: UINT32 = 2 << 16;
+pub const SYSTEM_TABLE_REVISION_2_60: UINT32 = (2 << 16) | 60;
 #[repr(C)]
 pub struct SYSTEM_TABLE {
     pub header: TABLE_HEADER,
     pub firmware_vendor: *const CHAR16,
     pub firmware_revision: UINT32,
     pub console_in_handle: HANDLE,
-    pub console_in: *const VOID,
+    pub console_in: *const SIMPLE_TEXT_INPUT_PROTOCOL,
     pub console_out_handle: HANDLE,
     pub console_out: *const SIMPLE_TEXT_OUTPUT_PROTOCOL,
     pub standard_error_handle: HANDLE,
@@ -121,10 +183,10 @@ pub struct BOOT_SERVICES {
     pub locate_device_path: *const VOID,
     pub install_configuration_table: *const VOID,
     // Image services
-    pub load_image: *const VOID,
-    pub start_image: *const VOID,
+    pub load_image: IMAGE_LOAD,
+    pub start_image: IMAGE_START,
     pub exit: *const VOID,
-    pub unload_image: *const VOID,
+    pub unload_image: IMAGE_UNLOAD,
     pub exit_boot_services: EXIT_BOOT_SERVICES,
     // Miscellaneous Services
     pub get_next_montonic_count: *const VOID,
@@ -139,7 +201,7 @@ pub struct BOOT_SERVICES {
     pub open_protocol_information: *const VOID,
     // Library Services
     pub protocols_per_handle: *const VOID,
-    pub locate_handle_buffer: *const VOID,
+    pub locate_handle_buffer: LOCATE_HANDLE_BUFFER,
     pub locate_protocol: LOCATE_PROTOCOL,
     pub install_multiple_protocol_interfaces: *const VOID,
     pub uninstall_multiple_protocol_interfaces: *const VOID,
@@ -163,6 +225,55 @@ pub struct CONFIGURATION_TABLE {
     pub vendor_table: *const VOID,
 }
 
+pub const MEMORY_ATTRIBUTES_TABLE_VERSION: UINT32 = 0x00000002;
+pub const MEMORY_ATTRIBUTES_FLAGS_RT_FORWARD_CONTROL_FLOW_GUARD: UINT32 = 0x1;
+
+// Followed by `number_of_entries` descriptors, each `descriptor_size` bytes
+#[repr(C)]
+pub struct MEMORY_ATTRIBUTES_TABLE {
+    pub version: UINT32,
+    pub number_of_entries: UINT32,
+    pub descriptor_size: UINT32,
+    pub flags: UINT32,
+}
+
+/*
+ * ================================================================
+ * || 5.3 GUID Partition Table (GPT) Disk Layout
+ * ================================================================
+ */
+
+pub const PRIMARY_PARTITION_HEADER_LBA: LBA = 1;
+
+// "EFI PART"
+pub const PARTITION_TABLE_HEADER_SIGNATURE: UINT64 = 0x5452415020494645;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PARTITION_TABLE_HEADER {
+    pub header: TABLE_HEADER,
+    pub my_lba: LBA,
+    pub alternate_lba: LBA,
+    pub first_usable_lba: LBA,
+    pub last_usable_lba: LBA,
+    pub disk_guid: GUID,
+    pub partition_entry_lba: LBA,
+    pub number_of_partition_entries: UINT32,
+    pub size_of_partition_entry: UINT32,
+    pub partition_entry_array_crc32: UINT32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PARTITION_ENTRY {
+    pub partition_type_guid: GUID,
+    pub unique_partition_guid: GUID,
+    pub starting_lba: UINT64,
+    pub ending_lba: UINT64,
+    pub attributes: UINT64,
+    pub partition_name: [CHAR16; 36],
+}
+
 /*
  * ================================================================
  * || 7.2 Memory Allocation Services
@@ -258,6 +369,21 @@ pub type HANDLE_PROTOCOL = unsafe extern "efiapi" fn(
     interface: *mut *const VOID,
 ) -> STATUS;
 
+#[repr(C)]
+pub enum LOCATE_SEARCH_TYPE {
+    AllHandles,
+    ByRegisterNotify,
+    ByProtocol,
+}
+
+pub type LOCATE_HANDLE_BUFFER = unsafe extern "efiapi" fn(
+    search_type: LOCATE_SEARCH_TYPE,
+    protocol: *const GUID,
+    search_key: *const VOID,
+    no_handles: *mut UINTN,
+    buffer: *mut *const HANDLE,
+) -> STATUS;
+
 pub type LOCATE_PROTOCOL = unsafe extern "efiapi" fn(
     protocol: *const GUID,
     registration: *const VOID,
@@ -270,6 +396,20 @@ pub type LOCATE_PROTOCOL = unsafe extern "efiapi" fn(
  * ================================================================
  */
 
+pub type IMAGE_LOAD = unsafe extern "efiapi" fn(
+    boot_policy: BOOLEAN,
+    parent_image_handle: HANDLE,
+    device_path: *const DEVICE_PATH_PROTOCOL,
+    source_buffer: *const VOID,
+    source_size: UINTN,
+    image_handle: *mut HANDLE,
+) -> STATUS;
+pub type IMAGE_START = unsafe extern "efiapi" fn(
+    image_handle: HANDLE,
+    exit_data_size: *mut UINTN,
+    exit_data: *mut *const CHAR16,
+) -> STATUS;
+pub type IMAGE_UNLOAD = unsafe extern "efiapi" fn(image_handle: HANDLE) -> STATUS;
 pub type EXIT_BOOT_SERVICES =
     unsafe extern "efiapi" fn(image_handle: HANDLE, map_key: UINTN) -> STATUS;
 
@@ -296,18 +436,19 @@ pub type COPY_MEM =
  */
 
 #[repr(C)]
+#[derive(Debug, Clone, Copy)]
 pub struct TIME {
-    year: UINT16,
-    month: UINT8,
-    day: UINT8,
-    hour: UINT8,
-    minute: UINT8,
-    second: UINT8,
-    pad1: UINT8,
-    nanosecond: UINT32,
-    time_zone: INT16,
-    daylight: UINT8,
-    pad2: UINT8,
+    pub year: UINT16,
+    pub month: UINT8,
+    pub day: UINT8,
+    pub hour: UINT8,
+    pub minute: UINT8,
+    pub second: UINT8,
+    pub pad1: UINT8,
+    pub nanosecond: UINT32,
+    pub time_zone: INT16,
+    pub daylight: UINT8,
+    pub pad2: UINT8,
 }
 
 /*
@@ -330,7 +471,7 @@ pub struct LOADED_IMAGE_PROTOCOL {
     pub system_table: *const SYSTEM_TABLE,
     // Source location of the image
     pub device_handle: HANDLE,
-    pub file_path: *const VOID,
+    pub file_path: *const DEVICE_PATH_PROTOCOL,
     pub reserved: *const VOID,
     // Image's load options
     pub load_options_size: UINT32,
@@ -343,13 +484,111 @@ pub struct LOADED_IMAGE_PROTOCOL {
     pub unload: *const VOID,
 }
 
+/*
+ * ================================================================
+ * || 10.2 EFI Device Path Protocol
+ * ================================================================
+ */
+
+pub const DEVICE_PATH_PROTOCOL_GUID: GUID = GUID {
+    a: 0x09576E91,
+    b: 0x6D3F,
+    c: 0x11D2,
+    d: [0x8E, 0x39, 0x00, 0xA0, 0xC9, 0x69, 0x72, 0x3B],
+};
+
+#[repr(C)]
+pub struct DEVICE_PATH_PROTOCOL {
+    pub r#type: UINT8,
+    pub sub_type: UINT8,
+    pub length: [UINT8; 2],
+}
+
+/*
+ * ================================================================
+ * || 10.3 Device Path Nodes
+ * ================================================================
+ */
+
+pub const HARDWARE_DEVICE_PATH: UINT8 = 0x01;
+pub const ACPI_DEVICE_PATH: UINT8 = 0x02;
+pub const MESSAGING_DEVICE_PATH: UINT8 = 0x03;
+pub const MEDIA_DEVICE_PATH: UINT8 = 0x04;
+pub const BBS_DEVICE_PATH: UINT8 = 0x05;
+pub const END_DEVICE_PATH_TYPE: UINT8 = 0x7F;
+
+pub const END_INSTANCE_DEVICE_PATH_SUBTYPE: UINT8 = 0x01;
+pub const END_ENTIRE_DEVICE_PATH_SUBTYPE: UINT8 = 0xFF;
+
+pub const HW_PCI_DP: UINT8 = 0x01;
+pub const HW_MEMMAP_DP: UINT8 = 0x03;
+pub const HW_VENDOR_DP: UINT8 = 0x04;
+pub const HW_CONTROLLER_DP: UINT8 = 0x05;
+
+pub const ACPI_DP: UINT8 = 0x01;
+pub const ACPI_ADR_DP: UINT8 = 0x03;
+
+pub const MSG_ATAPI_DP: UINT8 = 0x01;
+pub const MSG_SCSI_DP: UINT8 = 0x02;
+pub const MSG_USB_DP: UINT8 = 0x05;
+pub const MSG_VENDOR_DP: UINT8 = 0x0A;
+pub const MSG_MAC_ADDR_DP: UINT8 = 0x0B;
+pub const MSG_SATA_DP: UINT8 = 0x12;
+pub const MSG_NVME_NAMESPACE_DP: UINT8 = 0x17;
+
+pub const MEDIA_HARDDRIVE_DP: UINT8 = 0x01;
+pub const MEDIA_CDROM_DP: UINT8 = 0x02;
+pub const MEDIA_VENDOR_DP: UINT8 = 0x03;
+pub const MEDIA_FILEPATH_DP: UINT8 = 0x04;
+pub const MEDIA_PROTOCOL_DP: UINT8 = 0x05;
+pub const MEDIA_PIWG_FW_FILE_DP: UINT8 = 0x06;
+pub const MEDIA_PIWG_FW_VOL_DP: UINT8 = 0x07;
+
+pub const MBR_TYPE_PCAT: UINT8 = 0x01;
+pub const MBR_TYPE_EFI_PARTITION_TABLE_HEADER: UINT8 = 0x02;
+
+pub const NO_DISK_SIGNATURE: UINT8 = 0x00;
+pub const SIGNATURE_TYPE_MBR: UINT8 = 0x01;
+pub const SIGNATURE_TYPE_GUID: UINT8 = 0x02;
+
+/*
+ * ================================================================
+ * || 12.3 Simple Text Input Protocol
+ * ================================================================
+ */
+
+pub const SIMPLE_TEXT_INPUT_PROTOCOL_GUID: GUID = GUID {
+    a: 0x387477C1,
+    b: 0x69C7,
+    c: 0x11D2,
+    d: [0x8E, 0x39, 0x00, 0xA0, 0xC9, 0x69, 0x72, 0x3B],
+};
+
+#[repr(C)]
+pub struct SIMPLE_TEXT_INPUT_PROTOCOL {
+    pub reset: *const VOID,
+    pub read_key_stroke: INPUT_READ_KEY,
+    pub wait_for_key: EVENT,
+}
+
+#[repr(C)]
+pub struct INPUT_KEY {
+    pub scan_code: UINT16,
+    pub unicode_char: CHAR16,
+}
+
+pub type INPUT_READ_KEY = unsafe extern "efiapi" fn(
+    this: *const SIMPLE_TEXT_INPUT_PROTOCOL,
+    key: *mut INPUT_KEY,
+) -> STATUS;
+
 /*
  * ================================================================
  * || 12.4 Simple Text Output Protocol
  * ================================================================
  */
 
-const SIMPLE_TEXT_OUTPUT_PROTOCOL_GUID: GUID = GUID {
+pub const SIMPLE_TEXT_OUTPUT_PROTOCOL_GUID: GUID = GUID {
     a: 0x387477C2,
     b: 0x69C7,
     c: 0x11D2,
@@ -358,22 +597,83 @@ const SIMPLE_TEXT_OUTPUT_PROTOCOL_GUID: GUID = GUID {
 
 #[repr(C)]
 pub struct SIMPLE_TEXT_OUTPUT_PROTOCOL {
-    pub reset: *const VOID,
+    pub reset: TEXT_RESET,
     pub output_string: TEXT_STRING,
-    pub test_string: *const VOID,
-    pub query_mode: *const VOID,
-    pub set_mode: *const VOID,
-    pub set_attribute: *const VOID,
+    pub test_string: TEXT_TEST_STRING,
+    pub query_mode: TEXT_QUERY_MODE,
+    pub set_mode: TEXT_SET_MODE,
+    pub set_attribute: TEXT_SET_ATTRIBUTE,
     pub clear_screen: TEXT_CLEAR_SCREEN,
     pub set_cursor_pos: TEXT_SET_CURSOR_POSITION,
-    pub enable_cursor: *const VOID,
-    pub mode: *const VOID,
+    pub enable_cursor: TEXT_ENABLE_CURSOR,
+    pub mode: *const SIMPLE_TEXT_OUTPUT_MODE,
+}
+
+#[repr(C)]
+pub struct SIMPLE_TEXT_OUTPUT_MODE {
+    pub max_mode: INT32,
+    pub mode: INT32,
+    pub attribute: INT32,
+    pub cursor_column: INT32,
+    pub cursor_row: INT32,
+    pub cursor_visible: BOOLEAN,
 }
 
+pub const BLACK: UINTN = 0x00;
+pub const BLUE: UINTN = 0x01;
+pub const GREEN: UINTN = 0x02;
+pub const CYAN: UINTN = 0x03;
+pub const RED: UINTN = 0x04;
+pub const MAGENTA: UINTN = 0x05;
+pub const BROWN: UINTN = 0x06;
+pub const LIGHTGRAY: UINTN = 0x07;
+pub const BRIGHT: UINTN = 0x08;
+pub const DARKGRAY: UINTN = 0x08;
+pub const LIGHTBLUE: UINTN = 0x09;
+pub const LIGHTGREEN: UINTN = 0x0A;
+pub const LIGHTCYAN: UINTN = 0x0B;
+pub const LIGHTRED: UINTN = 0x0C;
+pub const LIGHTMAGENTA: UINTN = 0x0D;
+pub const YELLOW: UINTN = 0x0E;
+pub const WHITE: UINTN = 0x0F;
+
+pub const BACKGROUND_BLACK: UINTN = 0x00;
+pub const BACKGROUND_BLUE: UINTN = 0x10;
+pub const BACKGROUND_GREEN: UINTN = 0x20;
+pub const BACKGROUND_CYAN: UINTN = 0x30;
+pub const BACKGROUND_RED: UINTN = 0x40;
+pub const BACKGROUND_MAGENTA: UINTN = 0x50;
+pub const BACKGROUND_BROWN: UINTN = 0x60;
+pub const BACKGROUND_LIGHTGRAY: UINTN = 0x70;
+
+pub const fn TEXT_ATTR(foreground: UINTN, background: UINTN) -> UINTN {
+    foreground | (background << 4)
+}
+
+pub type TEXT_RESET = unsafe extern "efiapi" fn(
+    this: *const SIMPLE_TEXT_OUTPUT_PROTOCOL,
+    extended_verification: BOOLEAN,
+) -> STATUS;
 pub type TEXT_STRING = unsafe extern "efiapi" fn(
     this: *const SIMPLE_TEXT_OUTPUT_PROTOCOL,
     string: *const CHAR16,
 ) -> STATUS;
+pub type TEXT_TEST_STRING = unsafe extern "efiapi" fn(
+    this: *const SIMPLE_TEXT_OUTPUT_PROTOCOL,
+    string: *const CHAR16,
+) -> STATUS;
+pub type TEXT_QUERY_MODE = unsafe extern "efiapi" fn(
+    this: *const SIMPLE_TEXT_OUTPUT_PROTOCOL,
+    mode_number: UINTN,
+    columns: *mut UINTN,
+    rows: *mut UINTN,
+) -> STATUS;
+pub type TEXT_SET_MODE = unsafe extern "efiapi" fn(
+    this: *const SIMPLE_TEXT_OUTPUT_PROTOCOL,
+    mode_number: UINTN,
+) -> STATUS;
+pub type TEXT_SET_ATTRIBUTE =
+    unsafe extern "efiapi" fn(this: *const SIMPLE_TEXT_OUTPUT_PROTOCOL, attribute: UINTN) -> STATUS;
 pub type TEXT_CLEAR_SCREEN =
     unsafe extern "efiapi" fn(this: *const SIMPLE_TEXT_OUTPUT_PROTOCOL) -> STATUS;
 pub type TEXT_SET_CURSOR_POSITION = unsafe extern "efiapi" fn(
@@ -381,6 +681,8 @@ pub type TEXT_SET_CURSOR_POSITION = unsafe extern "efiapi" fn(
     column: UINTN,
     row: UINTN,
 ) -> STATUS;
+pub type TEXT_ENABLE_CURSOR =
+    unsafe extern "efiapi" fn(this: *const SIMPLE_TEXT_OUTPUT_PROTOCOL, visible: BOOLEAN) -> STATUS;
 
 /*
  * ================================================================
@@ -409,10 +711,41 @@ pub enum GRAPHICS_PIXEL_FORMAT {
 pub struct GRAPHICS_OUTPUT_PROTOCOL {
     pub query_mode: *const VOID,
     pub set_mode: *const VOID,
-    pub blt: *const VOID,
+    pub blt: GRAPHICS_OUTPUT_PROTOCOL_BLT,
     pub mode: *const GRAPHICS_OUTPUT_PROTOCOL_MODE,
 }
 
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GRAPHICS_OUTPUT_BLT_PIXEL {
+    pub blue: UINT8,
+    pub green: UINT8,
+    pub red: UINT8,
+    pub reserved: UINT8,
+}
+
+#[repr(C)]
+pub enum GRAPHICS_OUTPUT_BLT_OPERATION {
+    BltVideoFill,
+    BltVideoToBltBuffer,
+    BltBufferToVideo,
+    BltVideoToVideo,
+    GraphicsOutputBltOperationMax,
+}
+
+pub type GRAPHICS_OUTPUT_PROTOCOL_BLT = unsafe extern "efiapi" fn(
+    this: *const GRAPHICS_OUTPUT_PROTOCOL,
+    blt_buffer: *mut GRAPHICS_OUTPUT_BLT_PIXEL,
+    blt_operation: GRAPHICS_OUTPUT_BLT_OPERATION,
+    source_x: UINTN,
+    source_y: UINTN,
+    destination_x: UINTN,
+    destination_y: UINTN,
+    width: UINTN,
+    height: UINTN,
+    delta: UINTN,
+) -> STATUS;
+
 #[repr(C)]
 pub struct GRAPHICS_OUTPUT_MODE_INFORMATION {
     pub version: UINT32,
@@ -441,6 +774,26 @@ pub struct GRAPHICS_OUTPUT_PROTOCOL_MODE {
     pub framebuffer_size: UINTN,
 }
 
+pub const EDID_DISCOVERED_PROTOCOL_GUID: GUID = GUID {
+    a: 0x1C0C34F6,
+    b: 0xD380,
+    c: 0x41FA,
+    d: [0xA0, 0x49, 0x8A, 0xD0, 0x6C, 0x1A, 0x66, 0xAA],
+};
+
+pub const EDID_ACTIVE_PROTOCOL_GUID: GUID = GUID {
+    a: 0xBD8C1056,
+    b: 0x9F36,
+    c: 0x44EC,
+    d: [0x92, 0xA8, 0xA6, 0x33, 0x7F, 0x81, 0x79, 0x86],
+};
+
+#[repr(C)]
+pub struct EDID_PROTOCOL {
+    pub size_of_edid: UINT32,
+    pub edid: *const UINT8,
+}
+
 /*
  * ================================================================
  * || 13.4 Simple File System Protocol
@@ -494,14 +847,14 @@ pub struct FILE_PROTOCOL {
     pub revision: UINT64,
     pub open: FILE_OPEN,
     pub close: FILE_CLOSE,
-    pub delete: *const VOID,
+    pub delete: FILE_DELETE,
     pub read: FILE_READ,
-    pub write: *const VOID,
-    pub get_position: *const VOID,
-    pub set_position: *const VOID,
+    pub write: FILE_WRITE,
+    pub get_position: FILE_GET_POSITION,
+    pub set_position: FILE_SET_POSITION,
     pub get_info: FILE_GET_INFO,
-    pub set_info: *const VOID,
-    pub flush: *const VOID,
+    pub set_info: FILE_SET_INFO,
+    pub flush: FILE_FLUSH,
     pub open_ex: *const VOID,
     pub read_ex: *const VOID,
     pub write_ex: *const VOID,
@@ -520,6 +873,23 @@ pub struct FILE_INFO {
     pub filename: CHAR16,
 }
 
+pub const FILE_SYSTEM_INFO_ID: GUID = GUID {
+    a: 0x09576E93,
+    b: 0x6D3F,
+    c: 0x11D2,
+    d: [0x8E, 0x39, 0x00, 0xA0, 0xC9, 0x69, 0x72, 0x3B],
+};
+
+#[repr(C)]
+pub struct FILE_SYSTEM_INFO {
+    pub size: UINT64,
+    pub read_only: BOOLEAN,
+    pub volume_size: UINT64,
+    pub free_space: UINT64,
+    pub block_size: UINT32,
+    pub volume_label: CHAR16,
+}
+
 pub type FILE_OPEN = unsafe extern "efiapi" fn(
     this: *const FILE_PROTOCOL,
     new_handle: *mut *const FILE_PROTOCOL,
@@ -539,6 +909,167 @@ pub type FILE_READ = unsafe extern "efiapi" fn(
     buffer_size: *mut UINTN,
     buffer: *mut VOID,
 ) -> STATUS;
+pub type FILE_WRITE = unsafe extern "efiapi" fn(
+    this: *const FILE_PROTOCOL,
+    buffer_size: *mut UINTN,
+    buffer: *const VOID,
+) -> STATUS;
+pub type FILE_DELETE = unsafe extern "efiapi" fn(this: *const FILE_PROTOCOL) -> STATUS;
+pub type FILE_FLUSH = unsafe extern "efiapi" fn(this: *const FILE_PROTOCOL) -> STATUS;
+pub type FILE_SET_INFO = unsafe extern "efiapi" fn(
+    this: *const FILE_PROTOCOL,
+    information_type: *const GUID,
+    buffer_size: UINTN,
+    buffer: *const VOID,
+) -> STATUS;
+pub type FILE_GET_POSITION =
+    unsafe extern "efiapi" fn(this: *const FILE_PROTOCOL, position: *mut UINT64) -> STATUS;
+pub type FILE_SET_POSITION =
+    unsafe extern "efiapi" fn(this: *const FILE_PROTOCOL, position: UINT64) -> STATUS;
+
+/*
+ * ================================================================
+ * || 13.7 Disk I/O Protocol
+ * ================================================================
+ */
+
+pub const DISK_IO_PROTOCOL_GUID: GUID = GUID {
+    a: 0xCE345171,
+    b: 0xBA0B,
+    c: 0x11D2,
+    d: [0x8E, 0x4F, 0x00, 0xA0, 0xC9, 0x69, 0x72, 0x3B],
+};
+
+#[repr(C)]
+pub struct DISK_IO_PROTOCOL {
+    pub revision: UINT64,
+    pub read_disk: DISK_READ,
+    pub write_disk: DISK_WRITE,
+}
+
+pub type DISK_READ = unsafe extern "efiapi" fn(
+    this: *const DISK_IO_PROTOCOL,
+    media_id: UINT32,
+    offset: UINT64,
+    buffer_size: UINTN,
+    buffer: *mut VOID,
+) -> STATUS;
+pub type DISK_WRITE = unsafe extern "efiapi" fn(
+    this: *const DISK_IO_PROTOCOL,
+    media_id: UINT32,
+    offset: UINT64,
+    buffer_size: UINTN,
+    buffer: *const VOID,
+) -> STATUS;
+
+/*
+ * ================================================================
+ * || 13.9 Block I/O Protocol
+ * ================================================================
+ */
+
+pub const BLOCK_IO_PROTOCOL_GUID: GUID = GUID {
+    a: 0x964E5B21,
+    b: 0x6459,
+    c: 0x11D2,
+    d: [0x8E, 0x39, 0x00, 0xA0, 0xC9, 0x69, 0x72, 0x3B],
+};
+
+#[repr(C)]
+pub struct BLOCK_IO_PROTOCOL {
+    pub revision: UINT64,
+    pub media: *const BLOCK_IO_MEDIA,
+    pub reset: BLOCK_RESET,
+    pub read_blocks: BLOCK_READ,
+    pub write_blocks: BLOCK_WRITE,
+    pub flush_blocks: BLOCK_FLUSH,
+}
+
+#[repr(C)]
+pub struct BLOCK_IO_MEDIA {
+    pub media_id: UINT32,
+    pub removable_media: BOOLEAN,
+    pub media_present: BOOLEAN,
+    pub logical_partition: BOOLEAN,
+    pub read_only: BOOLEAN,
+    pub write_caching: BOOLEAN,
+    pub block_size: UINT32,
+    pub io_align: UINT32,
+    pub last_block: LBA,
+    // Revision 2
+    pub lowest_aligned_lba: LBA,
+    pub logical_blocks_per_physical_block: UINT32,
+    // Revision 3
+    pub optimal_transfer_length_granularity: UINT32,
+}
+
+pub type BLOCK_RESET = unsafe extern "efiapi" fn(
+    this: *const BLOCK_IO_PROTOCOL,
+    extended_verification: BOOLEAN,
+) -> STATUS;
+pub type BLOCK_READ = unsafe extern "efiapi" fn(
+    this: *const BLOCK_IO_PROTOCOL,
+    media_id: UINT32,
+    lba: LBA,
+    buffer_size: UINTN,
+    buffer: *mut VOID,
+) -> STATUS;
+pub type BLOCK_WRITE = unsafe extern "efiapi" fn(
+    this: *const BLOCK_IO_PROTOCOL,
+    media_id: UINT32,
+    lba: LBA,
+    buffer_size: UINTN,
+    buffer: *const VOID,
+) -> STATUS;
+pub type BLOCK_FLUSH = unsafe extern "efiapi" fn(this: *const BLOCK_IO_PROTOCOL) -> STATUS;
+
+/*
+ * ================================================================
+ * || 13.18 Partition Information Protocol
+ * ================================================================
+ */
+
+pub const PARTITION_INFO_PROTOCOL_GUID: GUID = GUID {
+    a: 0x8CF2F62C,
+    b: 0xBC9B,
+    c: 0x4821,
+    d: [0x80, 0x8D, 0xEC, 0x9E, 0xC4, 0x21, 0xA1, 0xA0],
+};
+
+pub const PARTITION_TYPE_OTHER: UINT32 = 0x00;
+pub const PARTITION_TYPE_MBR: UINT32 = 0x01;
+pub const PARTITION_TYPE_GPT: UINT32 = 0x02;
+
+#[repr(C, packed)]
+pub struct PARTITION_INFO_PROTOCOL {
+    pub revision: UINT32,
+    pub partition_type: UINT32,
+    pub system: UINT8,
+    pub reserved: [UINT8; 7],
+    pub info: PARTITION_INFO,
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub union PARTITION_INFO {
+    pub mbr: MBR_PARTITION_RECORD,
+    pub gpt: PARTITION_ENTRY,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MBR_PARTITION_RECORD {
+    pub boot_indicator: UINT8,
+    pub start_head: UINT8,
+    pub start_sector: UINT8,
+    pub start_track: UINT8,
+    pub os_indicator: UINT8,
+    pub end_head: UINT8,
+    pub end_sector: UINT8,
+    pub end_track: UINT8,
+    pub starting_lba: [UINT8; 4],
+    pub size_in_lba: [UINT8; 4],
+}
 
 /*
  * ================================================================
@@ -550,101 +1081,184 @@ pub const fn ERROR(code: UINTN) -> UINTN {
     0x8000000000000000 | code
 }
 
-#[repr(usize)]
+#[repr(transparent)]
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-pub enum STATUS {
-    SUCCESS = 0,
-    WARN_UNKOWN_GLYPH = 1,
-    WARN_DELETE_FAILURE = 2,
-    WARN_WRITE_FAILURE = 3,
-    WARN_BUFFER_TOO_SMALL = 4,
-    WARN_STALE_DATA = 5,
-    WARN_FILE_SYSTEM = 6,
-    WARN_RESET_REQUIRED = 7,
-    LOAD_ERROR = ERROR(1),
-    INVALID_PARAMETER = ERROR(2),
-    UNSUPPORTED = ERROR(3),
-    BAD_BUFFER_SIZE = ERROR(4),
-    BUFFER_TOO_SMALL = ERROR(5),
-    NOT_READY = ERROR(6),
-    DEVICE_ERROR = ERROR(7),
-    WRITE_PROTECTED = ERROR(8),
-    OUT_OF_RESOURCES = ERROR(9),
-    VOLUME_CORRUPTED = ERROR(10),
-    VOLUME_FULL = ERROR(11),
-    NO_MEDIA = ERROR(12),
-    MEDIA_CHANGED = ERROR(13),
-    NOT_FOUND = ERROR(14),
-    ACCESS_DENIED = ERROR(15),
-    NO_RESPONSE = ERROR(16),
-    NO_MAPPING = ERROR(17),
-    TIMEOUT = ERROR(18),
-    NOT_STARTED = ERROR(19),
-    ALREADY_STARTED = ERROR(20),
-    ABORTED = ERROR(21),
-    ICMP_ERROR = ERROR(22),
-    TFTP_ERROR = ERROR(23),
-    PROTOCOL_ERROR = ERROR(24),
-    INCOMPATIBLE_VERSION = ERROR(25),
-    SECURITY_VIOLATION = ERROR(26),
-    CRC_ERROR = ERROR(27),
-    END_OF_MEDIA = ERROR(28),
-    END_OF_FILE = ERROR(31),
-    INVALID_LANGUAGE = ERROR(32),
-    COMPROMISED_DATA = ERROR(33),
-    IP_ADDRESS_CONFLICT = ERROR(34),
-    HTTP_ERROR = ERROR(35),
+pub struct STATUS(pub UINTN);
+
+impl STATUS {
+    pub const SUCCESS: STATUS = STATUS(0);
+    pub const WARN_UNKNOWN_GLYPH: STATUS = STATUS(1);
+    pub const WARN_DELETE_FAILURE: STATUS = STATUS(2);
+    pub const WARN_WRITE_FAILURE: STATUS = STATUS(3);
+    pub const WARN_BUFFER_TOO_SMALL: STATUS = STATUS(4);
+    pub const WARN_STALE_DATA: STATUS = STATUS(5);
+    pub const WARN_FILE_SYSTEM: STATUS = STATUS(6);
+    pub const WARN_RESET_REQUIRED: STATUS = STATUS(7);
+    pub const LOAD_ERROR: STATUS = STATUS(ERROR(1));
+    pub const INVALID_PARAMETER: STATUS = STATUS(ERROR(2));
+    pub const UNSUPPORTED: STATUS = STATUS(ERROR(3));
+    pub const BAD_BUFFER_SIZE: STATUS = STATUS(ERROR(4));
+    pub const BUFFER_TOO_SMALL: STATUS = STATUS(ERROR(5));
+    pub const NOT_READY: STATUS = STATUS(ERROR(6));
+    pub const DEVICE_ERROR: STATUS = STATUS(ERROR(7));
+    pub const WRITE_PROTECTED: STATUS = STATUS(ERROR(8));
+    pub const OUT_OF_RESOURCES: STATUS = STATUS(ERROR(9));
+    pub const VOLUME_CORRUPTED: STATUS = STATUS(ERROR(10));
+    pub const VOLUME_FULL: STATUS = STATUS(ERROR(11));
+    pub const NO_MEDIA: STATUS = STATUS(ERROR(12));
+    pub const MEDIA_CHANGED: STATUS = STATUS(ERROR(13));
+    pub const NOT_FOUND: STATUS = STATUS(ERROR(14));
+    pub const ACCESS_DENIED: STATUS = STATUS(ERROR(15));
+    pub const NO_RESPONSE: STATUS = STATUS(ERROR(16));
+    pub const NO_MAPPING: STATUS = STATUS(ERROR(17));
+    pub const TIMEOUT: STATUS = STATUS(ERROR(18));
+    pub const NOT_STARTED: STATUS = STATUS(ERROR(19));
+    pub const ALREADY_STARTED: STATUS = STATUS(ERROR(20));
+    pub const ABORTED: STATUS = STATUS(ERROR(21));
+    pub const ICMP_ERROR: STATUS = STATUS(ERROR(22));
+    pub const TFTP_ERROR: STATUS = STATUS(ERROR(23));
+    pub const PROTOCOL_ERROR: STATUS = STATUS(ERROR(24));
+    pub const INCOMPATIBLE_VERSION: STATUS = STATUS(ERROR(25));
+    pub const SECURITY_VIOLATION: STATUS = STATUS(ERROR(26));
+    pub const CRC_ERROR: STATUS = STATUS(ERROR(27));
+    pub const END_OF_MEDIA: STATUS = STATUS(ERROR(28));
+    pub const END_OF_FILE: STATUS = STATUS(ERROR(31));
+    pub const INVALID_LANGUAGE: STATUS = STATUS(ERROR(32));
+    pub const COMPROMISED_DATA: STATUS = STATUS(ERROR(33));
+    pub const IP_ADDRESS_CONFLICT: STATUS = STATUS(ERROR(34));
+    pub const HTTP_ERROR: STATUS = STATUS(ERROR(35));
+
+    pub const fn is_error(self) -> bool {
+        self.0 & ERROR(0) != 0
+    }
+
+    pub const fn is_warning(self) -> bool {
+        self.0 != 0 && !self.is_error()
+    }
+
+    // Warnings are treated as success
+    pub fn to_result(self, message: &'static str) -> Result<(), crate::Error> {
+        if self.is_error() {
+            Err(crate::Error::new(self, message))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn name(self) -> Option<&'static str> {
+        Some(match self {
+            STATUS::SUCCESS => "Success",
+            STATUS::WARN_UNKNOWN_GLYPH => "Unknown Glyph",
+            STATUS::WARN_DELETE_FAILURE => "Delete Failure",
+            STATUS::WARN_WRITE_FAILURE => "Write Failure",
+            STATUS::WARN_BUFFER_TOO_SMALL => "Buffer Too Small",
+            STATUS::WARN_STALE_DATA => "Stale Data",
+            STATUS::WARN_FILE_SYSTEM => "File System",
+            STATUS::WARN_RESET_REQUIRED => "Reset Required",
+            STATUS::LOAD_ERROR => "Load Error",
+            STATUS::INVALID_PARAMETER => "Invalid Parameter",
+            STATUS::UNSUPPORTED => "Unsupported",
+            STATUS::BAD_BUFFER_SIZE => "Bad Buffer Size",
+            STATUS::BUFFER_TOO_SMALL => "Buffer Too Small",
+            STATUS::NOT_READY => "Not Ready",
+            STATUS::DEVICE_ERROR => "Device Error",
+            STATUS::WRITE_PROTECTED => "Write Protected",
+            STATUS::OUT_OF_RESOURCES => "Out of Resources",
+            STATUS::VOLUME_CORRUPTED => "Volume Corrupted",
+            STATUS::VOLUME_FULL => "Volume Full",
+            STATUS::NO_MEDIA => "No Media",
+            STATUS::MEDIA_CHANGED => "Media Changed",
+            STATUS::NOT_FOUND => "Not Found",
+            STATUS::ACCESS_DENIED => "Access Denied",
+            STATUS::NO_RESPONSE => "No Response",
+            STATUS::NO_MAPPING => "No Mapping",
+            STATUS::TIMEOUT => "Timeout",
+            STATUS::NOT_STARTED => "Not Started",
+            STATUS::ALREADY_STARTED => "Already Started",
+            STATUS::ABORTED => "Aborted",
+            STATUS::ICMP_ERROR => "ICMP Error",
+            STATUS::TFTP_ERROR => "TFTP Error",
+            STATUS::PROTOCOL_ERROR => "Protocol Error",
+            STATUS::INCOMPATIBLE_VERSION => "Incompatible Version",
+            STATUS::SECURITY_VIOLATION => "Security Violation",
+            STATUS::CRC_ERROR => "CRC Error",
+            STATUS::END_OF_MEDIA => "End of Media",
+            STATUS::END_OF_FILE => "End of File",
+            STATUS::INVALID_LANGUAGE => "Invalid Language",
+            STATUS::COMPROMISED_DATA => "Compromised Data",
+            STATUS::IP_ADDRESS_CONFLICT => "IP Address Conflict",
+            STATUS::HTTP_ERROR => "HTTP Error",
+            _ => return None,
+        })
+    }
+}
+
+impl From<STATUS> for UINTN {
+    fn from(status: STATUS) -> Self {
+        status.0
+    }
 }
 
 impl core::fmt::Display for STATUS {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(
-            f,
-            "{} ({:#X})",
-            match self {
-                STATUS::SUCCESS => "Success",
-                STATUS::WARN_UNKOWN_GLYPH => "Unknown Glyph",
-                STATUS::WARN_DELETE_FAILURE => "Delete Failure",
-                STATUS::WARN_WRITE_FAILURE => "Write Failure",
-                STATUS::WARN_BUFFER_TOO_SMALL => "Buffer Too Small",
-                STATUS::WARN_STALE_DATA => "Stale Data",
-                STATUS::WARN_FILE_SYSTEM => "File System",
-                STATUS::WARN_RESET_REQUIRED => "Reset Required",
-                STATUS::LOAD_ERROR => "Load Error",
-                STATUS::INVALID_PARAMETER => "Invalid Parameter",
-                STATUS::UNSUPPORTED => "Unsupported",
-                STATUS::BAD_BUFFER_SIZE => "Bad Buffer Size",
-                STATUS::BUFFER_TOO_SMALL => "Buffer Too Small",
-                STATUS::NOT_READY => "Not Ready",
-                STATUS::DEVICE_ERROR => "Device Error",
-                STATUS::WRITE_PROTECTED => "Write Protected",
-                STATUS::OUT_OF_RESOURCES => "Out of Resources",
-                STATUS::VOLUME_CORRUPTED => "Volume Corrupted",
-                STATUS::VOLUME_FULL => "Volume Full",
-                STATUS::NO_MEDIA => "No Media",
-                STATUS::MEDIA_CHANGED => "Media Changed",
-                STATUS::NOT_FOUND => "Not Found",
-                STATUS::ACCESS_DENIED => "Access Denied",
-                STATUS::NO_RESPONSE => "No Response",
-                STATUS::NO_MAPPING => "No Mapping",
-                STATUS::TIMEOUT => "Timeout",
-                STATUS::NOT_STARTED => "Not Started",
-                STATUS::ALREADY_STARTED => "Already Started",
-                STATUS::ABORTED => "Aborted",
-                STATUS::ICMP_ERROR => "ICMP Error",
-                STATUS::TFTP_ERROR => "TFTP Error",
-                STATUS::PROTOCOL_ERROR => "Protocol Error",
-                STATUS::INCOMPATIBLE_VERSION => "Incompatible Version",
-                STATUS::SECURITY_VIOLATION => "Security Violation",
-                STATUS::CRC_ERROR => "CRC Error",
-                STATUS::END_OF_MEDIA => "End of Media",
-                STATUS::END_OF_FILE => "End of File",
-                STATUS::INVALID_LANGUAGE => "Invalid Language",
-                STATUS::COMPROMISED_DATA => "Compromised Data",
-                STATUS::IP_ADDRESS_CONFLICT => "IP Address Conflict",
-                STATUS::HTTP_ERROR => "HTTP Error",
-            },
-            *self as usize
-        )
+        match self.name() {
+            Some(name) => write!(f, "{} ({:#X})", name, self.0),
+            None if self.is_error() => write!(f, "Unknown Error ({:#X})", self.0),
+            None => write!(f, "Unknown Warning ({:#X})", self.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_guids() {
+        let guid: GUID = "4F68BCE3-E8CD-4DB1-A7CF-4BE2D1A5B2C4".parse().unwrap();
+        assert_eq!(
+            guid,
+            GUID {
+                a: 0x4F68BCE3,
+                b: 0xE8CD,
+                c: 0x4DB1,
+                d: [0xA7, 0xCF, 0x4B, 0xE2, 0xD1, 0xA5, 0xB2, 0xC4],
+            }
+        );
+        assert_eq!(
+            alloc::format!("{}", guid),
+            "4F68BCE3-E8CD-4DB1-A7CF-4BE2D1A5B2C4"
+        );
+        assert_eq!(
+            "4f68bce3-e8cd-4db1-a7cf-4be2d1a5b2c4"
+                .parse::<GUID>()
+                .unwrap(),
+            guid
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_guids() {
+        for s in &[
+            "",
+            "4F68BCE3-E8CD-4DB1-A7CF-4BE2D1A5B2C",
+            "4F68BCE3-E8CD-4DB1-A7CF-4BE2D1A5B2C45",
+            "4F68BCE3E-8CD-4DB1-A7CF-4BE2D1A5B2C4",
+            "4F68BCE3-E8CD-4DB1-A7CF-4BE2D1A5B2CG",
+            "+F68BCE3-E8CD-4DB1-A7CF-4BE2D1A5B2C4",
+        ] {
+            let err = s.parse::<GUID>().err().unwrap();
+            assert_eq!(err.status(), STATUS::INVALID_PARAMETER);
+        }
+    }
+
+    #[test]
+    fn rejects_non_ascii_guids() {
+        // 36 bytes with a two byte character at bytes 20 and 21, which the
+        // second group of four digits splits
+        let s = "4F68BCE3-E8CD-4DB1-AéF-4BE2D1A5B2C4";
+        assert_eq!(s.len(), 36);
+        let err = s.parse::<GUID>().err().unwrap();
+        assert_eq!(err.status(), STATUS::INVALID_PARAMETER);
     }
 }
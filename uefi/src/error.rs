@@ -0,0 +1,162 @@
+use crate::Status;
+use alloc::{
+    alloc::{alloc, Layout},
+    boxed::Box,
+};
+use core::fmt::{self, Write};
+
+const MESSAGE_BUFFER_SIZE: usize = 80;
+
+pub struct Error {
+    status: Status,
+    message: Message,
+    source: Option<Box<Error>>,
+}
+
+pub trait ResultExt<T> {
+    fn context(self, message: &'static str) -> Result<T, Error>;
+    fn with_context(self, args: fmt::Arguments) -> Result<T, Error>;
+}
+
+enum Message {
+    Static(&'static str),
+    Formatted(MessageBuffer),
+}
+
+// Formatted messages are kept inline so building an error never needs the allocator
+struct MessageBuffer {
+    buffer: [u8; MESSAGE_BUFFER_SIZE],
+    length: usize,
+}
+
+impl Error {
+    pub fn new(status: Status, message: &'static str) -> Self {
+        Error {
+            status,
+            message: Message::Static(message),
+            source: None,
+        }
+    }
+
+    pub fn format(status: Status, args: fmt::Arguments) -> Self {
+        let mut buffer = MessageBuffer {
+            buffer: [0; MESSAGE_BUFFER_SIZE],
+            length: 0,
+        };
+        buffer.write_fmt(args).ok();
+
+        Error {
+            status,
+            message: Message::Formatted(buffer),
+            source: None,
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    pub fn message(&self) -> &str {
+        match &self.message {
+            Message::Static(message) => message,
+            Message::Formatted(buffer) => buffer.as_str(),
+        }
+    }
+
+    pub fn source(&self) -> Option<&Error> {
+        self.source.as_deref()
+    }
+
+    pub fn chain(&self) -> impl Iterator<Item = &Error> {
+        core::iter::successors(Some(self), |error| error.source())
+    }
+
+    // Wraps `source` in this error, keeping the source's status. If the
+    // source can't be allocated it is dropped rather than failing again.
+    fn wrap(mut self, source: Error) -> Self {
+        self.status = source.status;
+        self.source = try_box(source);
+        self
+    }
+}
+
+impl<T> ResultExt<T> for Result<T, Error> {
+    fn context(self, message: &'static str) -> Result<T, Error> {
+        self.map_err(|error| Error::new(error.status, message).wrap(error))
+    }
+
+    fn with_context(self, args: fmt::Arguments) -> Result<T, Error> {
+        self.map_err(|error| Error::format(error.status, args).wrap(error))
+    }
+}
+
+impl MessageBuffer {
+    fn as_str(&self) -> &str {
+        // Writes only stop on character boundaries, so this is always valid
+        core::str::from_utf8(&self.buffer[..self.length]).unwrap_or("")
+    }
+}
+
+impl fmt::Write for MessageBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            let length = c.len_utf8();
+            if self.length + length > MESSAGE_BUFFER_SIZE {
+                return Err(fmt::Error);
+            }
+
+            c.encode_utf8(&mut self.buffer[self.length..]);
+            self.length += length;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.message(), self.status)?;
+
+        for source in self.chain().skip(1) {
+            write!(f, "\n    Caused by: {}", source.message())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl From<Error> for usize {
+    fn from(error: Error) -> Self {
+        error.status.0
+    }
+}
+
+impl From<Error> for Status {
+    fn from(error: Error) -> Self {
+        error.status
+    }
+}
+
+fn try_box(error: Error) -> Option<Box<Error>> {
+    let layout = Layout::new::<Error>();
+    let ptr = unsafe { alloc(layout) } as *mut Error;
+    if ptr.is_null() {
+        return None;
+    }
+
+    unsafe {
+        ptr.write(error);
+        Some(Box::from_raw(ptr))
+    }
+}
+
+#[macro_export]
+macro_rules! error {
+    ($status:expr, $($arg:tt)*) => ($crate::Error::format($status, format_args!($($arg)*)));
+}
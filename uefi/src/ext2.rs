@@ -0,0 +1,619 @@
+use crate::{block::BlockDevice, efi, file::FileInfo, ResultExt};
+use alloc::{string::String, vec, vec::Vec};
+
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const SUPERBLOCK_SIZE: usize = 1024;
+const MAGIC: u16 = 0xEF53;
+
+const ROOT_INODE: u32 = 2;
+const GOOD_OLD_INODE_SIZE: u64 = 128;
+const GROUP_DESCRIPTOR_SIZE: usize = 32;
+const MAXIMUM_LOG_BLOCK_SIZE: u32 = 6;
+
+const DIRECT_BLOCKS: u64 = 12;
+const INDIRECT_BLOCK: usize = 12;
+const DOUBLE_INDIRECT_BLOCK: usize = 13;
+const TRIPLE_INDIRECT_BLOCK: usize = 14;
+
+// Targets shorter than this are stored in the inode's block pointers
+const FAST_SYMLINK_SIZE: u64 = 60;
+const MAXIMUM_SYMLINKS: usize = 8;
+
+const FEATURE_INCOMPAT_FILETYPE: u32 = 0x0002;
+const FEATURE_INCOMPAT_FLEX_BG: u32 = 0x0200;
+const SUPPORTED_INCOMPAT_FEATURES: u32 = FEATURE_INCOMPAT_FILETYPE | FEATURE_INCOMPAT_FLEX_BG;
+
+const MODE_TYPE_MASK: u16 = 0xF000;
+const MODE_DIRECTORY: u16 = 0x4000;
+const MODE_REGULAR: u16 = 0x8000;
+const MODE_SYMLINK: u16 = 0xA000;
+const MODE_USER_WRITE: u16 = 0x0080;
+
+const SECONDS_PER_DAY: u32 = 86400;
+
+// Where the file system is read from, so it isn't tied to the firmware
+pub(crate) trait Disk {
+    fn read(&self, offset: u64, buffer: &mut [u8]) -> Result<(), crate::Error>;
+}
+
+// A read-only ext2 file system on a partition the firmware can't read itself
+pub(crate) struct Ext2<D = BlockDevice> {
+    device: D,
+    block_size: u64,
+    blocks_count: u64,
+    inodes_per_group: u32,
+    inode_size: u64,
+    inode_tables: Vec<u64>,
+    label: String,
+}
+
+pub(crate) struct Inode {
+    mode: u16,
+    size: u64,
+    sectors: u32,
+    access_time: u32,
+    change_time: u32,
+    modification_time: u32,
+    blocks: [u32; 15],
+}
+
+impl Disk for BlockDevice {
+    fn read(&self, offset: u64, buffer: &mut [u8]) -> Result<(), crate::Error> {
+        BlockDevice::read(self, offset, buffer)
+    }
+}
+
+impl<D: Disk> Ext2<D> {
+    pub fn open(device: D) -> Result<Self, crate::Error> {
+        let mut superblock = [0; SUPERBLOCK_SIZE];
+        device.read(SUPERBLOCK_OFFSET, &mut superblock)?;
+
+        if read_u16(&superblock, 56) != MAGIC {
+            return Err(crate::Error::new(
+                efi::STATUS::UNSUPPORTED,
+                "Partition is not ext2",
+            ));
+        }
+
+        let incompat = read_u32(&superblock, 96);
+        if incompat & !SUPPORTED_INCOMPAT_FEATURES != 0 {
+            return Err(crate::error!(
+                efi::STATUS::UNSUPPORTED,
+                "Unsupported ext2 features {:#X}",
+                incompat & !SUPPORTED_INCOMPAT_FEATURES
+            ));
+        }
+
+        let log_block_size = read_u32(&superblock, 24);
+        let blocks_count = read_u32(&superblock, 4) as u64;
+        let first_data_block = read_u32(&superblock, 20) as u64;
+        let blocks_per_group = read_u32(&superblock, 32) as u64;
+        let inodes_per_group = read_u32(&superblock, 40);
+        let inode_size = match read_u32(&superblock, 76) {
+            0 => GOOD_OLD_INODE_SIZE,
+            _ => read_u16(&superblock, 88) as u64,
+        };
+
+        if log_block_size > MAXIMUM_LOG_BLOCK_SIZE
+            || blocks_per_group == 0
+            || inodes_per_group == 0
+            || inode_size < GOOD_OLD_INODE_SIZE
+            || blocks_count <= first_data_block
+        {
+            return Err(crate::Error::new(
+                efi::STATUS::COMPROMISED_DATA,
+                "Invalid ext2 superblock",
+            ));
+        }
+
+        let block_size = 1024 << log_block_size;
+        let group_count = (blocks_count - first_data_block).div_ceil(blocks_per_group) as usize;
+
+        // The group descriptors start in the block after the superblock
+        let mut descriptors = vec![0; group_count * GROUP_DESCRIPTOR_SIZE];
+        device
+            .read((first_data_block + 1) * block_size, &mut descriptors)
+            .context("Failed to read ext2 group descriptors")?;
+        let inode_tables = descriptors
+            .chunks_exact(GROUP_DESCRIPTOR_SIZE)
+            .map(|descriptor| read_u32(descriptor, 8) as u64)
+            .collect();
+
+        let label = superblock[120..136]
+            .iter()
+            .take_while(|c| **c != 0)
+            .map(|c| *c as char)
+            .collect();
+
+        Ok(Ext2 {
+            device,
+            block_size,
+            blocks_count,
+            inodes_per_group,
+            inode_size,
+            inode_tables,
+            label,
+        })
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn size(&self) -> u64 {
+        self.blocks_count * self.block_size
+    }
+
+    pub fn read_inode(&self, number: u32) -> Result<Inode, crate::Error> {
+        let index = number.wrapping_sub(1);
+        let table = match self
+            .inode_tables
+            .get((index / self.inodes_per_group) as usize)
+        {
+            Some(table) if number != 0 => *table,
+            _ => return Err(corrupt()),
+        };
+
+        let mut data = [0; GOOD_OLD_INODE_SIZE as usize];
+        let offset =
+            table * self.block_size + (index % self.inodes_per_group) as u64 * self.inode_size;
+        self.device.read(offset, &mut data)?;
+
+        let mode = read_u16(&data, 0);
+        let mut size = read_u32(&data, 4) as u64;
+        // Regular files keep the high half of their size where directories keep their ACL
+        if mode & MODE_TYPE_MASK == MODE_REGULAR {
+            size |= (read_u32(&data, 108) as u64) << 32;
+        }
+
+        let mut blocks = [0; 15];
+        for (i, block) in blocks.iter_mut().enumerate() {
+            *block = read_u32(&data, 40 + i * 4);
+        }
+
+        Ok(Inode {
+            mode,
+            size,
+            sectors: read_u32(&data, 28),
+            access_time: read_u32(&data, 8),
+            change_time: read_u32(&data, 12),
+            modification_time: read_u32(&data, 16),
+            blocks,
+        })
+    }
+
+    // `path` must be normalised, symbolic links are followed
+    pub fn lookup(&self, path: &str) -> Result<Inode, crate::Error> {
+        let mut remaining: Vec<String> = path
+            .split('\\')
+            .filter(|component| !component.is_empty())
+            .rev()
+            .map(String::from)
+            .collect();
+        let mut parents = vec![self.read_inode(ROOT_INODE)?];
+        let mut links = 0;
+
+        while let Some(component) = remaining.pop() {
+            match component.as_str() {
+                "." => continue,
+                ".." => {
+                    if parents.len() > 1 {
+                        parents.pop();
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+
+            let directory = &parents[parents.len() - 1];
+            let number = self
+                .read_dir(directory)?
+                .into_iter()
+                .find(|(name, _)| *name == component)
+                .map(|(_, number)| number)
+                .ok_or_else(|| crate::Error::new(efi::STATUS::NOT_FOUND, "File not found"))?;
+            let inode = self.read_inode(number)?;
+
+            if inode.is_symlink() {
+                links += 1;
+                if links > MAXIMUM_SYMLINKS {
+                    return Err(crate::Error::new(
+                        efi::STATUS::NOT_FOUND,
+                        "Too many symbolic links",
+                    ));
+                }
+
+                let target = self.read_link(&inode)?;
+                if target.starts_with('/') {
+                    parents.truncate(1);
+                }
+                remaining.extend(
+                    target
+                        .split('/')
+                        .filter(|component| !component.is_empty())
+                        .rev()
+                        .map(String::from),
+                );
+                continue;
+            }
+
+            if !remaining.is_empty() && !inode.is_directory() {
+                return Err(crate::Error::new(
+                    efi::STATUS::NOT_FOUND,
+                    "Path component is not a directory",
+                ));
+            }
+
+            parents.push(inode);
+        }
+
+        Ok(parents.pop().unwrap())
+    }
+
+    // Returns the name and inode number of each entry, including "." and ".."
+    pub fn read_dir(&self, directory: &Inode) -> Result<Vec<(String, u32)>, crate::Error> {
+        if !directory.is_directory() {
+            return Err(crate::Error::new(
+                efi::STATUS::INVALID_PARAMETER,
+                "Not a directory",
+            ));
+        }
+
+        let mut data = vec![0; directory.size as usize];
+        self.read(directory, 0, &mut data)?;
+
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        while offset + 8 <= data.len() {
+            let number = read_u32(&data, offset);
+            let record_length = read_u16(&data, offset + 4) as usize;
+            let name_length = data[offset + 6] as usize;
+            if record_length < 8 || offset + 8 + name_length > data.len() {
+                return Err(corrupt());
+            }
+
+            if number != 0 {
+                let name = &data[offset + 8..offset + 8 + name_length];
+                entries.push((String::from_utf8_lossy(name).into_owned(), number));
+            }
+
+            offset += record_length;
+        }
+
+        Ok(entries)
+    }
+
+    // Returns the number of bytes read, which is short at the end of the file
+    pub fn read(
+        &self,
+        inode: &Inode,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<usize, crate::Error> {
+        let length = (inode.size.saturating_sub(offset)).min(buffer.len() as u64) as usize;
+
+        let mut done = 0;
+        while done < length {
+            let position = offset + done as u64;
+            let index = position / self.block_size;
+            let within = position % self.block_size;
+            let address = self.block_address(inode, index)?;
+
+            // Merge runs of consecutive blocks into a single read
+            let mut count = 1;
+            while done + ((count * self.block_size - within) as usize) < length
+                && address != 0
+                && self.block_address(inode, index + count)? == address + count
+            {
+                count += 1;
+            }
+
+            let size = ((count * self.block_size - within) as usize).min(length - done);
+            let chunk = &mut buffer[done..done + size];
+            if address == 0 {
+                // Sparse blocks read as zeros
+                chunk.fill(0);
+            } else {
+                self.device
+                    .read(address * self.block_size + within, chunk)?;
+            }
+
+            done += size;
+        }
+
+        Ok(length)
+    }
+
+    fn read_link(&self, inode: &Inode) -> Result<String, crate::Error> {
+        let mut target = vec![0; inode.size as usize];
+        if inode.size < FAST_SYMLINK_SIZE && inode.sectors == 0 {
+            for (i, byte) in target.iter_mut().enumerate() {
+                *byte = (inode.blocks[i / 4] >> ((i % 4) * 8)) as u8;
+            }
+        } else {
+            self.read(inode, 0, &mut target)?;
+        }
+
+        Ok(String::from_utf8_lossy(&target).into_owned())
+    }
+
+    fn block_address(&self, inode: &Inode, index: u64) -> Result<u64, crate::Error> {
+        if index < DIRECT_BLOCKS {
+            return Ok(inode.blocks[index as usize] as u64);
+        }
+
+        let per_block = self.block_size / 4;
+        let mut index = index - DIRECT_BLOCKS;
+        let (mut block, levels) = if index < per_block {
+            (inode.blocks[INDIRECT_BLOCK], 1)
+        } else if index - per_block < per_block * per_block {
+            index -= per_block;
+            (inode.blocks[DOUBLE_INDIRECT_BLOCK], 2)
+        } else {
+            index -= per_block + per_block * per_block;
+            if index >= per_block * per_block * per_block {
+                return Err(corrupt());
+            }
+            (inode.blocks[TRIPLE_INDIRECT_BLOCK], 3)
+        };
+
+        for level in (0..levels).rev() {
+            if block == 0 {
+                return Ok(0);
+            }
+
+            let divisor = per_block.pow(level);
+            let mut entry = [0; 4];
+            self.device.read(
+                block as u64 * self.block_size + index / divisor * 4,
+                &mut entry,
+            )?;
+            block = u32::from_le_bytes(entry);
+            index %= divisor;
+        }
+
+        Ok(block as u64)
+    }
+}
+
+impl Inode {
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn is_directory(&self) -> bool {
+        self.mode & MODE_TYPE_MASK == MODE_DIRECTORY
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.mode & MODE_TYPE_MASK == MODE_SYMLINK
+    }
+
+    pub fn info(&self, name: String) -> FileInfo {
+        let mut attributes = 0;
+        if self.is_directory() {
+            attributes |= efi::FILE_DIRECTORY;
+        }
+        if self.mode & MODE_USER_WRITE == 0 {
+            attributes |= efi::FILE_READ_ONLY;
+        }
+
+        FileInfo {
+            name,
+            size: self.size,
+            physical_size: self.sectors as u64 * 512,
+            attributes,
+            create_time: to_time(self.change_time),
+            last_access_time: to_time(self.access_time),
+            modification_time: to_time(self.modification_time),
+        }
+    }
+}
+
+// Converts seconds since the Unix epoch, in UTC
+fn to_time(seconds: u32) -> efi::TIME {
+    let days = (seconds / SECONDS_PER_DAY) as i64;
+    let time = seconds % SECONDS_PER_DAY;
+
+    // Days to a civil date, from Howard Hinnant's algorithm
+    let z = days + 719468;
+    let era = z / 146097;
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    efi::TIME {
+        year: year as u16,
+        month: month as u8,
+        day: day as u8,
+        hour: (time / 3600) as u8,
+        minute: (time / 60 % 60) as u8,
+        second: (time % 60) as u8,
+        pad1: 0,
+        nanosecond: 0,
+        time_zone: 0,
+        daylight: 0,
+        pad2: 0,
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+fn corrupt() -> crate::Error {
+    crate::Error::new(
+        efi::STATUS::VOLUME_CORRUPTED,
+        "The ext2 file system is corrupt",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Built by tests/fixtures/make_ext2.sh
+    const IMAGE: &[u8] = include_bytes!("../tests/fixtures/ext2.img");
+
+    impl Disk for &[u8] {
+        fn read(&self, offset: u64, buffer: &mut [u8]) -> Result<(), crate::Error> {
+            let offset = offset as usize;
+            match self.get(offset..offset + buffer.len()) {
+                Some(data) => {
+                    buffer.copy_from_slice(data);
+                    Ok(())
+                }
+                None => Err(crate::Error::new(
+                    efi::STATUS::INVALID_PARAMETER,
+                    "Read is past the end of the image",
+                )),
+            }
+        }
+    }
+
+    fn open() -> Ext2<&'static [u8]> {
+        Ext2::open(IMAGE).unwrap()
+    }
+
+    fn read_file(file_system: &Ext2<&[u8]>, path: &str) -> Vec<u8> {
+        let inode = file_system.lookup(path).unwrap();
+        let mut data = vec![0; inode.size() as usize];
+        assert_eq!(file_system.read(&inode, 0, &mut data).unwrap(), data.len());
+        data
+    }
+
+    fn pattern(length: usize, multiplier: usize, offset: usize) -> Vec<u8> {
+        (0..length)
+            .map(|i| (i * multiplier % 251 + offset) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn reads_superblock() {
+        let file_system = open();
+        assert_eq!(file_system.label(), "testfs");
+        assert_eq!(file_system.size(), 128 * 1024);
+    }
+
+    #[test]
+    fn looks_up_paths() {
+        let file_system = open();
+        assert_eq!(read_file(&file_system, "\\hello.txt"), b"Hello, world!\n");
+        assert_eq!(
+            read_file(&file_system, "\\dir\\nested\\file.txt"),
+            b"nested file\n"
+        );
+        assert_eq!(
+            read_file(&file_system, "\\dir\\.\\nested\\..\\nested\\file.txt"),
+            b"nested file\n"
+        );
+        assert!(file_system.lookup("\\dir").unwrap().is_directory());
+
+        let err = file_system.lookup("\\missing.txt").err().unwrap();
+        assert_eq!(err.status(), efi::STATUS::NOT_FOUND);
+        let err = file_system.lookup("\\hello.txt\\file.txt").err().unwrap();
+        assert_eq!(err.status(), efi::STATUS::NOT_FOUND);
+    }
+
+    #[test]
+    fn reads_directories() {
+        let file_system = open();
+        let root = file_system.lookup("\\").unwrap();
+        let mut names: Vec<String> = file_system
+            .read_dir(&root)
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        names.sort();
+
+        assert_eq!(
+            names,
+            [
+                ".",
+                "..",
+                "absolute-link",
+                "dir",
+                "fast-link",
+                "hello.txt",
+                "lost+found",
+                "single.bin",
+                "slow-link",
+                "sparse.bin",
+            ]
+        );
+
+        let file = file_system.lookup("\\hello.txt").unwrap();
+        assert!(file_system.read_dir(&file).is_err());
+    }
+
+    #[test]
+    fn reads_single_indirect_blocks() {
+        let file_system = open();
+        assert_eq!(
+            read_file(&file_system, "\\single.bin"),
+            pattern(20480, 7, 0)
+        );
+
+        // A read crossing from the direct blocks into the indirect ones
+        let inode = file_system.lookup("\\single.bin").unwrap();
+        let mut data = vec![0; 2048];
+        file_system
+            .read(&inode, 11 * 1024 + 512, &mut data)
+            .unwrap();
+        assert_eq!(
+            data,
+            &pattern(20480, 7, 0)[11 * 1024 + 512..13 * 1024 + 512]
+        );
+    }
+
+    #[test]
+    fn reads_double_indirect_blocks_and_holes() {
+        let file_system = open();
+        let data = read_file(&file_system, "\\sparse.bin");
+        assert_eq!(data.len(), 304 * 1024);
+
+        assert_eq!(&data[..4096], &pattern(4096, 13, 1)[..]);
+        assert!(data[4096..300 * 1024].iter().all(|byte| *byte == 0));
+        assert_eq!(&data[300 * 1024..], &pattern(4096, 13, 1)[..]);
+    }
+
+    #[test]
+    fn reads_short_at_end_of_file() {
+        let file_system = open();
+        let inode = file_system.lookup("\\hello.txt").unwrap();
+        let mut data = [0; 32];
+        assert_eq!(file_system.read(&inode, 7, &mut data).unwrap(), 7);
+        assert_eq!(&data[..7], b"world!\n");
+        assert_eq!(file_system.read(&inode, 100, &mut data).unwrap(), 0);
+    }
+
+    #[test]
+    fn follows_symlinks() {
+        let file_system = open();
+        assert_eq!(read_file(&file_system, "\\fast-link"), b"nested file\n");
+        assert_eq!(read_file(&file_system, "\\slow-link"), b"nested file\n");
+        assert_eq!(
+            read_file(&file_system, "\\absolute-link"),
+            b"Hello, world!\n"
+        );
+    }
+}
@@ -0,0 +1,653 @@
+use crate::{config_table, efi, graphics::GraphicsMode};
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::convert::TryInto;
+
+const MAGIC: u32 = 0xD00DFEED;
+const HEADER_SIZE: usize = 40;
+// Version 17 added the struct block size, which the reader relies on
+const VERSION: u32 = 17;
+const LAST_COMPATIBLE_VERSION: u32 = 16;
+
+const BEGIN_NODE: u32 = 1;
+const END_NODE: u32 = 2;
+const PROP: u32 = 3;
+const NOP: u32 = 4;
+const END: u32 = 9;
+
+// Anything larger is assumed to be a bad header rather than copied
+const MAXIMUM_SIZE: usize = 0x1000000;
+
+// A device tree read into memory so it can be edited and written back out
+pub struct Fdt {
+    pub boot_cpuid: u32,
+    pub reservations: Vec<(u64, u64)>,
+    pub root: Node,
+}
+
+pub struct Node {
+    pub name: String,
+    pub properties: Vec<Property>,
+    pub children: Vec<Node>,
+}
+
+pub struct Property {
+    pub name: String,
+    pub value: Vec<u8>,
+}
+
+// The firmware's DTB, if it installed one
+pub fn get_firmware_blob() -> Result<&'static [u8], crate::Error> {
+    let address = config_table::get_config_table(config_table::DEVICE_TREE_GUID)?;
+    if address.is_null() {
+        return Err(crate::Error::new(
+            efi::STATUS::NOT_FOUND,
+            "Device tree table is null",
+        ));
+    }
+
+    let header = unsafe { core::slice::from_raw_parts(address as *const u8, HEADER_SIZE) };
+    if read_u32(header, 0) != MAGIC {
+        return Err(invalid("Invalid device tree magic"));
+    }
+
+    let size = read_u32(header, 4) as usize;
+    if !(HEADER_SIZE..=MAXIMUM_SIZE).contains(&size) {
+        return Err(invalid("Invalid device tree size"));
+    }
+
+    Ok(unsafe { core::slice::from_raw_parts(address as *const u8, size) })
+}
+
+impl Fdt {
+    pub fn parse(data: &[u8]) -> Result<Self, crate::Error> {
+        if data.len() < HEADER_SIZE || read_u32(data, 0) != MAGIC {
+            return Err(invalid("Invalid device tree magic"));
+        }
+
+        let total_size = read_u32(data, 4) as usize;
+        let struct_offset = read_u32(data, 8) as usize;
+        let strings_offset = read_u32(data, 12) as usize;
+        let reservations_offset = read_u32(data, 16) as usize;
+        let version = read_u32(data, 20);
+        let last_compatible_version = read_u32(data, 24);
+        let strings_size = read_u32(data, 32) as usize;
+        let struct_size = read_u32(data, 36) as usize;
+
+        if version < VERSION || last_compatible_version > VERSION {
+            return Err(crate::error!(
+                efi::STATUS::UNSUPPORTED,
+                "Unsupported device tree version {}",
+                version
+            ));
+        }
+
+        let in_bounds = |offset: usize, size: usize| {
+            offset
+                .checked_add(size)
+                .map_or(false, |end| end <= total_size)
+        };
+        if total_size > data.len()
+            || reservations_offset % 8 != 0
+            || struct_offset % 4 != 0
+            || !in_bounds(reservations_offset, 16)
+            || !in_bounds(struct_offset, struct_size)
+            || !in_bounds(strings_offset, strings_size)
+        {
+            return Err(invalid("Invalid device tree header"));
+        }
+
+        // The reservation block ends with an empty entry
+        let mut reservations = Vec::new();
+        let mut offset = reservations_offset;
+        loop {
+            if !in_bounds(offset, 16) {
+                return Err(invalid("Unterminated device tree reservation block"));
+            }
+
+            let address = read_u64(data, offset);
+            let size = read_u64(data, offset + 8);
+            if address == 0 && size == 0 {
+                break;
+            }
+            reservations.push((address, size));
+            offset += 16;
+        }
+
+        let mut reader = Reader {
+            data: &data[struct_offset..struct_offset + struct_size],
+            strings: &data[strings_offset..strings_offset + strings_size],
+            offset: 0,
+        };
+
+        let root = match reader.next_token()? {
+            BEGIN_NODE => reader.read_node()?,
+            _ => return Err(invalid("Device tree has no root node")),
+        };
+
+        if reader.next_token()? != END {
+            return Err(invalid("Device tree has more than one root node"));
+        }
+
+        Ok(Fdt {
+            boot_cpuid: read_u32(data, 28),
+            reservations,
+            root,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut structure = Vec::new();
+        let mut strings = Vec::new();
+        write_node(&self.root, &mut structure, &mut strings);
+        structure.extend_from_slice(&END.to_be_bytes());
+
+        let reservations_offset = HEADER_SIZE;
+        let struct_offset = reservations_offset + (self.reservations.len() + 1) * 16;
+        let strings_offset = struct_offset + structure.len();
+        let total_size = strings_offset + strings.len();
+
+        let mut data = Vec::with_capacity(total_size);
+        for field in [
+            MAGIC,
+            total_size as u32,
+            struct_offset as u32,
+            strings_offset as u32,
+            reservations_offset as u32,
+            VERSION,
+            LAST_COMPATIBLE_VERSION,
+            self.boot_cpuid,
+            strings.len() as u32,
+            structure.len() as u32,
+        ] {
+            data.extend_from_slice(&field.to_be_bytes());
+        }
+
+        for (address, size) in self.reservations.iter().chain([(0, 0)].iter()) {
+            data.extend_from_slice(&address.to_be_bytes());
+            data.extend_from_slice(&size.to_be_bytes());
+        }
+
+        data.extend_from_slice(&structure);
+        data.extend_from_slice(&strings);
+        data
+    }
+
+    // Creates /chosen if the tree doesn't have it
+    pub fn chosen(&mut self) -> &mut Node {
+        self.root.child_or_insert("chosen")
+    }
+
+    pub fn set_bootargs(&mut self, bootargs: &str) {
+        self.chosen().set_string("bootargs", bootargs);
+    }
+
+    // `end` is the first byte after the initrd, as Linux expects
+    pub fn set_initrd(&mut self, start: u64, end: u64) {
+        let chosen = self.chosen();
+        chosen.set_property("linux,initrd-start", start.to_be_bytes().to_vec());
+        chosen.set_property("linux,initrd-end", end.to_be_bytes().to_vec());
+    }
+
+    // Describes the framebuffer as a simple-framebuffer under /chosen. Only
+    // 32-bit RGB and BGR formats can be described this way.
+    pub fn set_framebuffer(&mut self, mode: &GraphicsMode) -> Result<(), crate::Error> {
+        let format = if mode.pixel_format
+            == efi::GRAPHICS_PIXEL_FORMAT::PixelRedGreenBlueReserved8BitPerColor as u32
+        {
+            "x8b8g8r8"
+        } else if mode.pixel_format
+            == efi::GRAPHICS_PIXEL_FORMAT::PixelBlueGreenRedReserved8BitPerColor as u32
+        {
+            "x8r8g8b8"
+        } else {
+            return Err(crate::Error::new(
+                efi::STATUS::UNSUPPORTED,
+                "Framebuffer format can't be described in a device tree",
+            ));
+        };
+
+        // Child nodes of /chosen use the root's cell sizes
+        let address_cells = self.root.get_u32("#address-cells").unwrap_or(2);
+        let size_cells = self.root.get_u32("#size-cells").unwrap_or(1);
+        let address = mode.framebuffer as u64;
+        let mut reg = to_cells(address, address_cells);
+        reg.extend(to_cells(mode.framebuffer_size as u64, size_cells));
+
+        let node = self
+            .chosen()
+            .child_or_insert(&format!("framebuffer@{:x}", address));
+        node.set_string("compatible", "simple-framebuffer");
+        node.set_property("reg", reg);
+        node.set_property("width", mode.horizontal_resolution.to_be_bytes().to_vec());
+        node.set_property("height", mode.vertical_resolution.to_be_bytes().to_vec());
+        node.set_property(
+            "stride",
+            (mode.pixels_per_scanline * 4).to_be_bytes().to_vec(),
+        );
+        node.set_string("format", format);
+        node.set_string("status", "okay");
+        Ok(())
+    }
+}
+
+impl Node {
+    pub fn child(&self, name: &str) -> Option<&Node> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    pub fn child_or_insert(&mut self, name: &str) -> &mut Node {
+        let index = match self.children.iter().position(|child| child.name == name) {
+            Some(index) => index,
+            None => {
+                self.children.push(Node {
+                    name: name.to_string(),
+                    properties: Vec::new(),
+                    children: Vec::new(),
+                });
+                self.children.len() - 1
+            }
+        };
+
+        &mut self.children[index]
+    }
+
+    pub fn property(&self, name: &str) -> Option<&[u8]> {
+        self.properties
+            .iter()
+            .find(|property| property.name == name)
+            .map(|property| property.value.as_slice())
+    }
+
+    pub fn get_u32(&self, name: &str) -> Option<u32> {
+        self.property(name)
+            .filter(|value| value.len() == 4)
+            .map(|value| read_u32(value, 0))
+    }
+
+    // Replaces the property if it already exists
+    pub fn set_property(&mut self, name: &str, value: Vec<u8>) {
+        match self
+            .properties
+            .iter_mut()
+            .find(|property| property.name == name)
+        {
+            Some(property) => property.value = value,
+            None => self.properties.push(Property {
+                name: name.to_string(),
+                value,
+            }),
+        }
+    }
+
+    pub fn set_string(&mut self, name: &str, value: &str) {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.push(0);
+        self.set_property(name, bytes);
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    strings: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    // Skips NOPs, which are left behind by in-place edits
+    fn next_token(&mut self) -> Result<u32, crate::Error> {
+        loop {
+            let token = self.read_u32()?;
+            if token != NOP {
+                return Ok(token);
+            }
+        }
+    }
+
+    // Reads the rest of a node after its BEGIN_NODE token
+    fn read_node(&mut self) -> Result<Node, crate::Error> {
+        let name = self.read_string()?;
+        let mut node = Node {
+            name,
+            properties: Vec::new(),
+            children: Vec::new(),
+        };
+
+        loop {
+            match self.next_token()? {
+                PROP => {
+                    let length = self.read_u32()? as usize;
+                    let name_offset = self.read_u32()? as usize;
+                    let value = self.read_bytes(length)?.to_vec();
+                    node.properties.push(Property {
+                        name: self.read_name(name_offset)?,
+                        value,
+                    });
+                }
+                BEGIN_NODE => node.children.push(self.read_node()?),
+                END_NODE => return Ok(node),
+                _ => return Err(invalid("Invalid device tree token")),
+            }
+        }
+    }
+
+    fn read_u32(&mut self) -> Result<u32, crate::Error> {
+        let bytes = self.read_bytes(4)?;
+        Ok(read_u32(bytes, 0))
+    }
+
+    // Advances past the padding to the next token
+    fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], crate::Error> {
+        let bytes = self
+            .data
+            .get(self.offset..self.offset + length)
+            .ok_or_else(|| invalid("Truncated device tree structure block"))?;
+        self.offset += (length + 3) & !3;
+        Ok(bytes)
+    }
+
+    fn read_string(&mut self) -> Result<String, crate::Error> {
+        let remaining = self.data.get(self.offset..).unwrap_or(&[]);
+        let length = remaining
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or_else(|| invalid("Unterminated device tree node name"))?;
+        let name = String::from_utf8_lossy(&remaining[..length]).to_string();
+        self.read_bytes(length + 1)?;
+        Ok(name)
+    }
+
+    fn read_name(&self, offset: usize) -> Result<String, crate::Error> {
+        let remaining = self
+            .strings
+            .get(offset..)
+            .ok_or_else(|| invalid("Invalid device tree property name"))?;
+        let length = remaining
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or_else(|| invalid("Unterminated device tree property name"))?;
+        Ok(String::from_utf8_lossy(&remaining[..length]).to_string())
+    }
+}
+
+fn write_node(node: &Node, structure: &mut Vec<u8>, strings: &mut Vec<u8>) {
+    structure.extend_from_slice(&BEGIN_NODE.to_be_bytes());
+    structure.extend_from_slice(node.name.as_bytes());
+    structure.push(0);
+    pad(structure);
+
+    for property in &node.properties {
+        structure.extend_from_slice(&PROP.to_be_bytes());
+        structure.extend_from_slice(&(property.value.len() as u32).to_be_bytes());
+        structure.extend_from_slice(&(string_offset(strings, &property.name) as u32).to_be_bytes());
+        structure.extend_from_slice(&property.value);
+        pad(structure);
+    }
+
+    for child in &node.children {
+        write_node(child, structure, strings);
+    }
+
+    structure.extend_from_slice(&END_NODE.to_be_bytes());
+}
+
+// Property names are shared between properties in the strings block
+fn string_offset(strings: &mut Vec<u8>, name: &str) -> usize {
+    let mut offset = 0;
+    for existing in strings.split(|byte| *byte == 0) {
+        if existing == name.as_bytes() && offset < strings.len() {
+            return offset;
+        }
+        offset += existing.len() + 1;
+    }
+
+    let offset = strings.len();
+    strings.extend_from_slice(name.as_bytes());
+    strings.push(0);
+    offset
+}
+
+fn pad(data: &mut Vec<u8>) {
+    while data.len() % 4 != 0 {
+        data.push(0);
+    }
+}
+
+fn to_cells(value: u64, cells: u32) -> Vec<u8> {
+    match cells {
+        1 => (value as u32).to_be_bytes().to_vec(),
+        _ => value.to_be_bytes().to_vec(),
+    }
+}
+
+fn invalid(message: &'static str) -> crate::Error {
+    crate::Error::new(efi::STATUS::COMPROMISED_DATA, message)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_be_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODEL: &str = "test,board";
+
+    // Builds a blob by hand so parsing isn't only checked against to_bytes
+    fn blob(bootargs: Option<&str>) -> Vec<u8> {
+        let strings = b"#address-cells\0#size-cells\0model\0reg\0bootargs\0";
+        let mut structure = Vec::new();
+        let token = |structure: &mut Vec<u8>, token: u32| {
+            structure.extend_from_slice(&token.to_be_bytes());
+        };
+        let begin = |structure: &mut Vec<u8>, name: &str| {
+            token(structure, BEGIN_NODE);
+            structure.extend_from_slice(name.as_bytes());
+            structure.push(0);
+            pad(structure);
+        };
+        let property = |structure: &mut Vec<u8>, name_offset: u32, value: &[u8]| {
+            token(structure, PROP);
+            token(structure, value.len() as u32);
+            token(structure, name_offset);
+            structure.extend_from_slice(value);
+            pad(structure);
+        };
+
+        begin(&mut structure, "");
+        property(&mut structure, 0, &2u32.to_be_bytes());
+        property(&mut structure, 15, &1u32.to_be_bytes());
+        property(&mut structure, 27, b"test,board\0");
+        token(&mut structure, NOP);
+        begin(&mut structure, "cpus");
+        begin(&mut structure, "cpu@0");
+        property(&mut structure, 33, &0u32.to_be_bytes());
+        token(&mut structure, END_NODE);
+        token(&mut structure, END_NODE);
+        if let Some(bootargs) = bootargs {
+            begin(&mut structure, "chosen");
+            property(&mut structure, 37, &[bootargs.as_bytes(), b"\0"].concat());
+            token(&mut structure, END_NODE);
+        }
+        token(&mut structure, END_NODE);
+        token(&mut structure, END);
+
+        let reservations_offset = HEADER_SIZE;
+        let struct_offset = reservations_offset + 32;
+        let strings_offset = struct_offset + structure.len();
+        let total_size = strings_offset + strings.len();
+
+        let mut data = Vec::new();
+        for field in [
+            MAGIC,
+            total_size as u32,
+            struct_offset as u32,
+            strings_offset as u32,
+            reservations_offset as u32,
+            17,
+            16,
+            1,
+            strings.len() as u32,
+            structure.len() as u32,
+        ] {
+            data.extend_from_slice(&field.to_be_bytes());
+        }
+        for value in [0x8000_0000u64, 0x10_0000, 0, 0] {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        data.extend_from_slice(&structure);
+        data.extend_from_slice(strings);
+        data
+    }
+
+    fn assert_same(a: &Node, b: &Node) {
+        assert_eq!(a.name, b.name);
+        assert_eq!(a.properties.len(), b.properties.len());
+        for (a, b) in a.properties.iter().zip(b.properties.iter()) {
+            assert_eq!((&a.name, &a.value), (&b.name, &b.value));
+        }
+        assert_eq!(a.children.len(), b.children.len());
+        for (a, b) in a.children.iter().zip(b.children.iter()) {
+            assert_same(a, b);
+        }
+    }
+
+    fn write_u32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+    }
+
+    #[test]
+    fn parses_blob() {
+        let fdt = Fdt::parse(&blob(None)).unwrap();
+        assert_eq!(fdt.boot_cpuid, 1);
+        assert_eq!(fdt.reservations, [(0x8000_0000, 0x10_0000)]);
+        assert_eq!(fdt.root.name, "");
+        assert_eq!(fdt.root.get_u32("#address-cells"), Some(2));
+        assert_eq!(fdt.root.get_u32("#size-cells"), Some(1));
+        assert_eq!(fdt.root.property("model"), Some(&b"test,board\0"[..]));
+
+        let cpu = fdt.root.child("cpus").and_then(|cpus| cpus.child("cpu@0"));
+        assert_eq!(cpu.and_then(|cpu| cpu.get_u32("reg")), Some(0));
+        assert!(fdt.root.child("chosen").is_none());
+    }
+
+    #[test]
+    fn round_trips() {
+        let fdt = Fdt::parse(&blob(Some("quiet"))).unwrap();
+        let data = fdt.to_bytes();
+        let parsed = Fdt::parse(&data).unwrap();
+
+        assert_eq!(parsed.boot_cpuid, fdt.boot_cpuid);
+        assert_eq!(parsed.reservations, fdt.reservations);
+        assert_same(&parsed.root, &fdt.root);
+        assert_eq!(parsed.to_bytes(), data);
+    }
+
+    #[test]
+    fn patches_existing_chosen() {
+        let mut fdt = Fdt::parse(&blob(Some("quiet"))).unwrap();
+        fdt.set_bootargs("console=ttyS0 root=/dev/vda");
+        fdt.set_initrd(0x4000_0000, 0x4080_0000);
+
+        let fdt = Fdt::parse(&fdt.to_bytes()).unwrap();
+        let chosen: Vec<_> = fdt
+            .root
+            .children
+            .iter()
+            .filter(|child| child.name == "chosen")
+            .collect();
+        assert_eq!(chosen.len(), 1);
+        assert_eq!(
+            chosen[0].property("bootargs"),
+            Some(&b"console=ttyS0 root=/dev/vda\0"[..])
+        );
+        assert_eq!(
+            chosen[0].property("linux,initrd-start"),
+            Some(&0x4000_0000u64.to_be_bytes()[..])
+        );
+        assert_eq!(
+            chosen[0].property("linux,initrd-end"),
+            Some(&0x4080_0000u64.to_be_bytes()[..])
+        );
+        assert_eq!(chosen[0].properties.len(), 3);
+    }
+
+    #[test]
+    fn creates_chosen() {
+        let mut fdt = Fdt::parse(&blob(None)).unwrap();
+        fdt.set_bootargs("quiet");
+        fdt.set_initrd(0x1000, 0x2000);
+
+        let fdt = Fdt::parse(&fdt.to_bytes()).unwrap();
+        let chosen = fdt.root.child("chosen").unwrap();
+        assert_eq!(chosen.property("bootargs"), Some(&b"quiet\0"[..]));
+        assert_eq!(
+            chosen.property("linux,initrd-start"),
+            Some(&0x1000u64.to_be_bytes()[..])
+        );
+        assert_eq!(
+            chosen.property("linux,initrd-end"),
+            Some(&0x2000u64.to_be_bytes()[..])
+        );
+
+        // The rest of the tree is untouched
+        assert_eq!(
+            fdt.root.property("model"),
+            Some(&[MODEL.as_bytes(), b"\0"].concat()[..])
+        );
+        assert!(fdt.root.child("cpus").is_some());
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut data = blob(None);
+        write_u32(&mut data, 0, 0xEDFE0DD0);
+        let err = Fdt::parse(&data).err().unwrap();
+        assert_eq!(err.status(), efi::STATUS::COMPROMISED_DATA);
+
+        let err = Fdt::parse(&data[..HEADER_SIZE - 1]).err().unwrap();
+        assert_eq!(err.status(), efi::STATUS::COMPROMISED_DATA);
+    }
+
+    #[test]
+    fn rejects_bad_version() {
+        let mut data = blob(None);
+        write_u32(&mut data, 20, 16);
+        let err = Fdt::parse(&data).err().unwrap();
+        assert_eq!(err.status(), efi::STATUS::UNSUPPORTED);
+
+        let mut data = blob(None);
+        write_u32(&mut data, 24, 18);
+        let err = Fdt::parse(&data).err().unwrap();
+        assert_eq!(err.status(), efi::STATUS::UNSUPPORTED);
+    }
+
+    #[test]
+    fn rejects_bad_size() {
+        let data = blob(None);
+        let err = Fdt::parse(&data[..data.len() - 1]).err().unwrap();
+        assert_eq!(err.status(), efi::STATUS::COMPROMISED_DATA);
+
+        // A struct block running past the total size
+        let mut data = blob(None);
+        let struct_size = read_u32(&data, 36);
+        let total_size = data.len() as u32;
+        write_u32(&mut data, 36, total_size);
+        let err = Fdt::parse(&data).err().unwrap();
+        assert_eq!(err.status(), efi::STATUS::COMPROMISED_DATA);
+
+        // A struct block cut short of the END token
+        let mut data = blob(None);
+        write_u32(&mut data, 36, struct_size - 4);
+        let err = Fdt::parse(&data).err().unwrap();
+        assert_eq!(err.status(), efi::STATUS::COMPROMISED_DATA);
+    }
+}
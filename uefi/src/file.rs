@@ -1,8 +1,96 @@
-use crate::efi::{self, CHAR16};
-use alloc::{vec, vec::Vec};
+use crate::{
+    block::BlockDevice,
+    config_table::GUID,
+    device_path::{DevicePath, Node, PartitionSignature},
+    efi,
+    ext2::{Ext2, Inode},
+    gpt::Gpt,
+    string::{CStr16, CString16},
+    ResultExt,
+};
+use alloc::{string::String, vec, vec::Vec};
 use core::ptr::{null, null_mut};
 
+pub type Time = efi::TIME;
+
+pub type Progress<'a> = &'a mut dyn FnMut(u64, u64);
+
+const READ_CHUNK_SIZE: usize = 0x100000;
+
+// Setting the position to this value moves it to the end of the file
+pub const END_OF_FILE: u64 = u64::MAX;
+
+pub struct FileInfo {
+    pub name: String,
+    pub size: u64,
+    pub physical_size: u64,
+    pub attributes: u64,
+    pub create_time: Time,
+    pub last_access_time: Time,
+    pub modification_time: Time,
+}
+
+pub struct File {
+    inner: FileInner,
+}
+
+pub struct Directory {
+    inner: DirectoryInner,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileSystem {
+    // Anything the firmware has a driver for, usually FAT
+    Firmware,
+    // Read through our own read-only driver
+    Ext2,
+}
+
+pub struct Volume {
+    pub file_system: FileSystem,
+    pub boot: bool,
+    pub partition_guid: Option<GUID>,
+    pub partition_label: Option<String>,
+    pub label: String,
+    pub read_only: bool,
+    pub size: u64,
+    handle: efi::HANDLE,
+}
+
+// Paths can start with "part=<guid>:", "partlabel=<label>:" or "label=<label>:"
+// to pick a volume other than the one we were loaded from
+enum VolumeSelector<'a> {
+    PartitionGuid(GUID),
+    PartitionLabel(&'a str),
+    Label(&'a str),
+}
+
+enum FileInner {
+    Firmware(*const efi::FILE_PROTOCOL),
+    Ext2 {
+        file_system: Ext2,
+        inode: Inode,
+        name: String,
+        position: u64,
+    },
+}
+
+enum DirectoryInner {
+    Firmware {
+        handle: *const efi::FILE_PROTOCOL,
+        buffer: Vec<u64>,
+    },
+    Ext2(vec::IntoIter<FileInfo>),
+}
+
+// An open volume root, which is closed on drop unless it is the boot volume
+struct Root {
+    handle: *const efi::FILE_PROTOCOL,
+    owned: bool,
+}
+
 static mut BOOT_VOLUME: Option<*const efi::FILE_PROTOCOL> = None;
+static mut BOOT_DEVICE: efi::HANDLE = null();
 static mut ALLOCATION_TYPE: efi::MEMORY_TYPE = efi::MEMORY_TYPE::ReservedMemoryType;
 
 pub fn initialize(
@@ -18,33 +106,14 @@ pub fn initialize(
             &mut loaded_image as *mut *const _ as *mut *const efi::VOID,
         )
     };
-    if status != efi::STATUS::SUCCESS {
-        return Err(crate::Error::new(status, "Failed to get loaded image"));
-    }
+    status.to_result("Failed to get loaded image")?;
 
-    // Get the file system protocol
-    let mut simple_file_system: *const efi::SIMPLE_FILE_SYSTEM_PROTOCOL = null();
-    let status = unsafe {
-        (boot_services.handle_protocol)(
-            (*loaded_image).device_handle,
-            &efi::SIMPLE_FILE_SYSTEM_PROTOCOL_GUID,
-            &mut simple_file_system as *mut *const _ as *mut *const efi::VOID,
-        )
-    };
-    if status != efi::STATUS::SUCCESS {
-        return Err(crate::Error::new(status, "Failed to get file system"));
-    }
-
-    let mut bv: *const efi::FILE_PROTOCOL = null();
-    let status = unsafe {
-        ((*simple_file_system).open_volume)(simple_file_system, &mut bv as *mut *const _)
-    };
-    if status != efi::STATUS::SUCCESS {
-        return Err(crate::Error::new(status, "Failed to get boot volume"));
-    }
+    let device = unsafe { (*loaded_image).device_handle };
+    let bv = open_volume(device).context("Failed to get boot volume")?;
 
     unsafe {
         BOOT_VOLUME = Some(crate::from_pointer(bv));
+        BOOT_DEVICE = device;
         ALLOCATION_TYPE = (*loaded_image).image_data_type;
     }
 
@@ -52,102 +121,806 @@ pub fn initialize(
 }
 
 pub fn load_file(path: &str) -> Result<Vec<u8>, crate::Error> {
-    // Get the boot volume
-    let boot_volume = unsafe {
-        match BOOT_VOLUME {
-            None => {
-                return Err(crate::Error::new(
-                    efi::STATUS::NOT_READY,
-                    "Failed to open file",
-                ))
-            }
-            Some(boot_volume) => boot_volume,
-        }
-    };
+    // The file is closed when it is dropped, even if the read fails
+    let mut file = File::open(path)?;
 
-    // Convert the filename
-    let mut wpath = Vec::with_capacity(path.len() + 1);
-    for c in path.chars() {
-        wpath.push(c as CHAR16);
-    }
-    wpath.push(0);
+    file.read_to_end(None)
+        .with_context(format_args!("Failed to read \"{}\"", path))
+}
 
-    // Open the file
-    let file_handle = open(boot_volume, &wpath)?;
+// Lists every volume with a file system the firmware or we understand
+pub fn volumes() -> Result<Vec<Volume>, crate::Error> {
+    let handles = crate::locate_handles(&efi::SIMPLE_FILE_SYSTEM_PROTOCOL_GUID)?;
 
-    // Read the file
-    let data = read(file_handle)?;
+    let mut volumes = Vec::with_capacity(handles.len());
+    for handle in &handles {
+        // Volumes that can't be opened, such as empty drives, are skipped
+        if let Ok(volume) = Volume::new(*handle) {
+            volumes.push(volume);
+        }
+    }
 
-    // Close the file
-    close(boot_volume, file_handle)?;
+    // Partitions the firmware has no driver for may still hold ext2
+    for device in crate::block::block_devices()? {
+        if !device.is_partition() || handles.contains(&device.handle()) {
+            continue;
+        }
 
-    Ok(data)
+        let handle = device.handle();
+        if let Ok(file_system) = Ext2::open(device) {
+            volumes.push(Volume::from_ext2(handle, &file_system));
+        }
+    }
+
+    Ok(volumes)
 }
 
 fn open(
-    boot_volume: *const efi::FILE_PROTOCOL,
-    name: &Vec<CHAR16>,
+    volume: *const efi::FILE_PROTOCOL,
+    name: &CStr16,
+) -> Result<*const efi::FILE_PROTOCOL, crate::Error> {
+    open_with_mode(
+        volume,
+        name,
+        efi::FILE_MODE_READ,
+        efi::FILE_READ_ONLY | efi::FILE_HIDDEN | efi::FILE_SYSTEM,
+    )
+}
+
+fn open_with_mode(
+    volume: *const efi::FILE_PROTOCOL,
+    name: &CStr16,
+    mode: u64,
+    attributes: u64,
 ) -> Result<*const efi::FILE_PROTOCOL, crate::Error> {
     let mut ret = null();
-    let status = unsafe {
-        ((*boot_volume).open)(
-            boot_volume,
-            &mut ret,
-            name.as_ptr(),
-            efi::FILE_MODE_READ,
-            efi::FILE_READ_ONLY | efi::FILE_HIDDEN | efi::FILE_SYSTEM,
-        )
-    };
-    if status != efi::STATUS::SUCCESS {
-        Err(crate::Error::new(status, "Failed to open file"))
+    let status = unsafe { ((*volume).open)(volume, &mut ret, name.as_ptr(), mode, attributes) };
+    if mode & efi::FILE_MODE_WRITE != 0 {
+        to_write_result(status, "Failed to open file")?;
     } else {
-        Ok(ret)
+        status.to_result("Failed to open file")?;
     }
+    Ok(ret)
 }
 
-fn close(
-    boot_volume: *const efi::FILE_PROTOCOL,
-    handle: *const efi::FILE_PROTOCOL,
-) -> Result<(), crate::Error> {
-    let status = unsafe { ((*boot_volume).close)(handle) };
-    if status != efi::STATUS::SUCCESS {
-        Err(crate::Error::new(status, "Failed to close file"))
-    } else {
+// Gives writes to read-only media a clearer message than the bare status
+fn to_write_result(status: efi::STATUS, message: &'static str) -> Result<(), crate::Error> {
+    match status {
+        efi::STATUS::WRITE_PROTECTED => {
+            Err(crate::Error::new(status, "The volume is write protected"))
+        }
+        efi::STATUS::ACCESS_DENIED => Err(crate::Error::new(status, "The file is read only")),
+        efi::STATUS::VOLUME_FULL => Err(crate::Error::new(status, "The volume is full")),
+        _ => status.to_result(message),
+    }
+}
+
+fn close(handle: *const efi::FILE_PROTOCOL) -> Result<(), crate::Error> {
+    let status = unsafe { ((*handle).close)(handle) };
+    status.to_result("Failed to close file")
+}
+
+pub fn exists(path: &str) -> Result<bool, crate::Error> {
+    match File::open(path) {
+        Ok(_) => Ok(true),
+        Err(error) if error.status() == efi::STATUS::NOT_FOUND => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+pub fn metadata(path: &str) -> Result<FileInfo, crate::Error> {
+    File::open(path)?
+        .info()
+        .with_context(format_args!("Failed to get metadata for \"{}\"", path))
+}
+
+pub fn delete(path: &str) -> Result<(), crate::Error> {
+    File::open_writable(path)?
+        .delete()
+        .with_context(format_args!("Failed to delete \"{}\"", path))
+}
+
+// The full firmware device path of `path`, including its volume
+pub fn device_path(path: &str) -> Result<DevicePath, crate::Error> {
+    let (device, _, rest) = find_volume(path)?;
+
+    let mut device_path = DevicePath::for_handle(device)?;
+    device_path.append_file_path(&normalize_path(rest))?;
+    Ok(device_path)
+}
+
+pub fn create_dir(path: &str) -> Result<(), crate::Error> {
+    let (device, file_system, rest) = find_volume(path)?;
+    if file_system != FileSystem::Firmware {
+        return Err(read_only_volume());
+    }
+    let root = open_root(device)?;
+    let wpath = convert_path(rest)?;
+
+    let handle = open_with_mode(
+        root.handle,
+        &wpath,
+        efi::FILE_MODE_READ | efi::FILE_MODE_WRITE | efi::FILE_MODE_CREATE,
+        efi::FILE_DIRECTORY,
+    )
+    .with_context(format_args!("Failed to create \"{}\"", path))?;
+    close(handle)
+}
+
+// Accepts either separator, removes empty and "." components and resolves ".."
+pub fn normalize_path(path: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split(|c| c == '\\' || c == '/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            _ => components.push(component),
+        }
+    }
+
+    let mut normalized = String::with_capacity(path.len() + 1);
+    for component in components {
+        normalized.push('\\');
+        normalized.push_str(component);
+    }
+
+    if normalized.is_empty() {
+        normalized.push('\\');
+    }
+
+    normalized
+}
+
+impl FileInfo {
+    pub fn is_directory(&self) -> bool {
+        self.attributes & efi::FILE_DIRECTORY != 0
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.attributes & efi::FILE_READ_ONLY != 0
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.attributes & efi::FILE_HIDDEN != 0
+    }
+
+    // `buffer` must hold a complete FILE_INFO, including the file name
+    fn from_buffer(buffer: &[u64]) -> Self {
+        let info = unsafe { &*(buffer.as_ptr() as *const efi::FILE_INFO) };
+
+        FileInfo {
+            name: unsafe { CStr16::from_ptr(&info.filename) }.to_string_lossy(),
+            size: info.file_size,
+            physical_size: info.physical_size,
+            attributes: info.attribute,
+            create_time: info.create_time,
+            last_access_time: info.last_access_time,
+            modification_time: info.modification_time,
+        }
+    }
+}
+
+impl File {
+    pub fn open(path: &str) -> Result<Self, crate::Error> {
+        let (device, file_system, rest) = find_volume(path)?;
+
+        let inner = match file_system {
+            FileSystem::Firmware => {
+                let root = open_root(device)?;
+                let handle = open(root.handle, &convert_path(rest)?)
+                    .with_context(format_args!("Failed to open \"{}\"", path))?;
+                FileInner::Firmware(handle)
+            }
+            FileSystem::Ext2 => {
+                let rest = normalize_path(rest);
+                let file_system = open_ext2(device)?;
+                let inode = file_system
+                    .lookup(&rest)
+                    .with_context(format_args!("Failed to open \"{}\"", path))?;
+                FileInner::Ext2 {
+                    file_system,
+                    inode,
+                    name: String::from(rest.rsplit('\\').next().unwrap_or("")),
+                    position: 0,
+                }
+            }
+        };
+
+        Ok(File { inner })
+    }
+
+    // Opens `path` for writing, creating it if it doesn't exist
+    pub fn open_writable(path: &str) -> Result<Self, crate::Error> {
+        let (device, file_system, rest) = find_volume(path)?;
+        if file_system != FileSystem::Firmware {
+            return Err(read_only_volume());
+        }
+        let root = open_root(device)?;
+
+        let handle = open_with_mode(
+            root.handle,
+            &convert_path(rest)?,
+            efi::FILE_MODE_READ | efi::FILE_MODE_WRITE | efi::FILE_MODE_CREATE,
+            0,
+        )
+        .with_context(format_args!("Failed to open \"{}\" for writing", path))?;
+        Ok(File {
+            inner: FileInner::Firmware(handle),
+        })
+    }
+
+    // Opens `path` for writing and truncates it
+    pub fn create(path: &str) -> Result<Self, crate::Error> {
+        let mut file = File::open_writable(path)?;
+        file.set_size(0)
+            .with_context(format_args!("Failed to truncate \"{}\"", path))?;
+        Ok(file)
+    }
+
+    pub fn info(&self) -> Result<FileInfo, crate::Error> {
+        match &self.inner {
+            FileInner::Firmware(handle) => get_file_info(*handle),
+            FileInner::Ext2 { inode, name, .. } => Ok(inode.info(name.clone())),
+        }
+    }
+
+    pub fn set_size(&mut self, size: u64) -> Result<(), crate::Error> {
+        let handle = self.writable_handle()?;
+        let mut buffer = get_file_info_buffer(handle)?;
+        let info = unsafe { &mut *(buffer.as_mut_ptr() as *mut efi::FILE_INFO) };
+        info.file_size = size;
+
+        let status = unsafe {
+            ((*handle).set_info)(
+                handle,
+                &efi::FILE_INFO_ID,
+                info.size as usize,
+                buffer.as_ptr() as *const efi::VOID,
+            )
+        };
+        to_write_result(status, "Failed to set file size")
+    }
+
+    pub fn write(&mut self, buffer: &[u8]) -> Result<usize, crate::Error> {
+        let handle = self.writable_handle()?;
+        let mut size = buffer.len();
+        let status =
+            unsafe { ((*handle).write)(handle, &mut size, buffer.as_ptr() as *const efi::VOID) };
+        to_write_result(status, "Failed to write file")?;
+        Ok(size)
+    }
+
+    pub fn write_all(&mut self, buffer: &[u8]) -> Result<(), crate::Error> {
+        let mut offset = 0;
+        while offset < buffer.len() {
+            let written = self.write(&buffer[offset..])?;
+            if written == 0 {
+                return Err(crate::Error::new(
+                    efi::STATUS::DEVICE_ERROR,
+                    "Failed to write file",
+                ));
+            }
+
+            offset += written;
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), crate::Error> {
+        let handle = self.writable_handle()?;
+        let status = unsafe { ((*handle).flush)(handle) };
+        to_write_result(status, "Failed to flush file")
+    }
+
+    // Deleting always closes the handle, even if the delete fails
+    pub fn delete(self) -> Result<(), crate::Error> {
+        let handle = self.writable_handle()?;
+        core::mem::forget(self);
+
+        let status = unsafe { ((*handle).delete)(handle) };
+        if status == efi::STATUS::WARN_DELETE_FAILURE {
+            return Err(crate::Error::new(status, "Failed to delete file"));
+        }
+        to_write_result(status, "Failed to delete file")
+    }
+
+    pub fn size(&self) -> Result<u64, crate::Error> {
+        Ok(self.info()?.size)
+    }
+
+    pub fn position(&self) -> Result<u64, crate::Error> {
+        match &self.inner {
+            FileInner::Firmware(handle) => {
+                let mut position = 0;
+                let status = unsafe { ((**handle).get_position)(*handle, &mut position) };
+                status.to_result("Failed to get file position")?;
+                Ok(position)
+            }
+            FileInner::Ext2 { position, .. } => Ok(*position),
+        }
+    }
+
+    pub fn set_position(&mut self, position: u64) -> Result<(), crate::Error> {
+        match &mut self.inner {
+            FileInner::Firmware(handle) => {
+                let status = unsafe { ((**handle).set_position)(*handle, position) };
+                status.to_result("Failed to set file position")
+            }
+            FileInner::Ext2 {
+                inode,
+                position: current,
+                ..
+            } => {
+                *current = if position == END_OF_FILE {
+                    inode.size()
+                } else {
+                    position
+                };
+                Ok(())
+            }
+        }
+    }
+
+    // Returns the number of bytes read, which is zero at the end of the file
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, crate::Error> {
+        let length = buffer.len().min(READ_CHUNK_SIZE);
+        match &mut self.inner {
+            FileInner::Firmware(handle) => {
+                let mut size = length;
+                let status = unsafe {
+                    ((**handle).read)(*handle, &mut size, buffer.as_mut_ptr() as *mut efi::VOID)
+                };
+                status.to_result("Failed to read file")?;
+                Ok(size)
+            }
+            FileInner::Ext2 {
+                file_system,
+                inode,
+                position,
+                ..
+            } => {
+                if inode.is_directory() {
+                    return Err(crate::Error::new(
+                        efi::STATUS::INVALID_PARAMETER,
+                        "Failed to read file",
+                    ));
+                }
+
+                let size = file_system
+                    .read(inode, *position, &mut buffer[..length])
+                    .context("Failed to read file")?;
+                *position += size as u64;
+                Ok(size)
+            }
+        }
+    }
+
+    // Fills all of `buffer` from the current position, in chunks
+    pub fn read_exact(
+        &mut self,
+        buffer: &mut [u8],
+        mut progress: Option<Progress>,
+    ) -> Result<(), crate::Error> {
+        let total = buffer.len() as u64;
+        let mut offset = 0;
+        while offset < buffer.len() {
+            let read = self.read(&mut buffer[offset..])?;
+            if read == 0 {
+                return Err(crate::Error::new(
+                    efi::STATUS::END_OF_FILE,
+                    "Unexpected end of file",
+                ));
+            }
+
+            offset += read;
+            if let Some(progress) = progress.as_mut() {
+                progress(offset as u64, total);
+            }
+        }
+
         Ok(())
     }
+
+    // Fills `buffer` from `offset`, e.g. a slice over pages from
+    // `allocate_pages` so large files don't go through the pool
+    pub fn read_at(
+        &mut self,
+        offset: u64,
+        buffer: &mut [u8],
+        progress: Option<Progress>,
+    ) -> Result<(), crate::Error> {
+        self.set_position(offset)?;
+        self.read_exact(buffer, progress)
+    }
+
+    pub fn read_to_end(&mut self, progress: Option<Progress>) -> Result<Vec<u8>, crate::Error> {
+        let size = self.size()?.saturating_sub(self.position()?) as usize;
+        let mut data = vec![0; size];
+        self.read_exact(&mut data, progress)?;
+        Ok(data)
+    }
 }
 
-fn read(handle: *const efi::FILE_PROTOCOL) -> Result<Vec<u8>, crate::Error> {
-    let mut file_info_size: efi::UINTN = 0;
-    let status = unsafe {
-        ((*handle).get_info)(handle, &efi::FILE_INFO_ID, &mut file_info_size, null_mut())
+impl Drop for File {
+    fn drop(&mut self) {
+        if let FileInner::Firmware(handle) = self.inner {
+            close(handle).ok();
+        }
+    }
+}
+
+impl File {
+    fn writable_handle(&self) -> Result<*const efi::FILE_PROTOCOL, crate::Error> {
+        match self.inner {
+            FileInner::Firmware(handle) => Ok(handle),
+            FileInner::Ext2 { .. } => Err(read_only_volume()),
+        }
+    }
+}
+
+impl Directory {
+    pub fn open(path: &str) -> Result<Self, crate::Error> {
+        let (device, file_system, rest) = find_volume(path)?;
+
+        let inner = match file_system {
+            FileSystem::Firmware => {
+                let root = open_root(device)?;
+                let handle = open(root.handle, &convert_path(rest)?)
+                    .with_context(format_args!("Failed to open \"{}\"", path))?;
+                // Wrapped first so the handle is closed if this isn't a directory
+                let directory = Directory {
+                    inner: DirectoryInner::Firmware {
+                        handle,
+                        buffer: Vec::new(),
+                    },
+                };
+
+                if !get_file_info(handle)?.is_directory() {
+                    return Err(not_a_directory(path));
+                }
+                return Ok(directory);
+            }
+            FileSystem::Ext2 => {
+                let file_system = open_ext2(device)?;
+                let directory = file_system
+                    .lookup(&normalize_path(rest))
+                    .with_context(format_args!("Failed to open \"{}\"", path))?;
+                if !directory.is_directory() {
+                    return Err(not_a_directory(path));
+                }
+
+                let mut entries = Vec::new();
+                for (name, number) in file_system.read_dir(&directory)? {
+                    if name != "." && name != ".." {
+                        entries.push(file_system.read_inode(number)?.info(name));
+                    }
+                }
+                DirectoryInner::Ext2(entries.into_iter())
+            }
+        };
+
+        Ok(Directory { inner })
+    }
+
+    fn read_entry(&mut self) -> Result<Option<FileInfo>, crate::Error> {
+        let (handle, buffer) = match &mut self.inner {
+            DirectoryInner::Firmware { handle, buffer } => (*handle, buffer),
+            DirectoryInner::Ext2(entries) => return Ok(entries.next()),
+        };
+
+        loop {
+            let mut size = buffer.len() * core::mem::size_of::<u64>();
+            let status = unsafe {
+                ((*handle).read)(handle, &mut size, buffer.as_mut_ptr() as *mut efi::VOID)
+            };
+
+            if status == efi::STATUS::BUFFER_TOO_SMALL {
+                *buffer = vec![0; size.div_ceil(core::mem::size_of::<u64>())];
+                continue;
+            }
+            status.to_result("Failed to read directory")?;
+
+            // A read of zero bytes marks the end of the directory
+            if size == 0 {
+                return Ok(None);
+            }
+
+            return Ok(Some(FileInfo::from_buffer(buffer)));
+        }
+    }
+}
+
+impl Iterator for Directory {
+    type Item = Result<FileInfo, crate::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.read_entry() {
+                Ok(Some(info)) if info.name == "." || info.name == ".." => continue,
+                Ok(Some(info)) => return Some(Ok(info)),
+                Ok(None) => return None,
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+}
+
+impl Drop for Directory {
+    fn drop(&mut self) {
+        if let DirectoryInner::Firmware { handle, .. } = self.inner {
+            close(handle).ok();
+        }
+    }
+}
+
+impl Volume {
+    fn new(handle: efi::HANDLE) -> Result<Self, crate::Error> {
+        let root = Root {
+            handle: open_volume(handle)?,
+            owned: true,
+        };
+
+        let buffer = get_info_buffer(root.handle, &efi::FILE_SYSTEM_INFO_ID)?;
+        let info = unsafe { &*(buffer.as_ptr() as *const efi::FILE_SYSTEM_INFO) };
+        let (partition_guid, partition_label) = get_partition_info(handle);
+
+        Ok(Volume {
+            file_system: FileSystem::Firmware,
+            boot: handle == unsafe { BOOT_DEVICE },
+            partition_guid,
+            partition_label,
+            label: unsafe { CStr16::from_ptr(&info.volume_label) }.to_string_lossy(),
+            read_only: info.read_only != efi::FALSE,
+            size: info.volume_size,
+            handle,
+        })
+    }
+
+    fn from_ext2(handle: efi::HANDLE, file_system: &Ext2) -> Self {
+        let (partition_guid, partition_label) = get_partition_info(handle);
+
+        Volume {
+            file_system: FileSystem::Ext2,
+            boot: false,
+            partition_guid,
+            partition_label,
+            label: String::from(file_system.label()),
+            read_only: true,
+            size: file_system.size(),
+            handle,
+        }
+    }
+}
+
+impl VolumeSelector<'_> {
+    fn matches(&self, volume: &Volume) -> bool {
+        match self {
+            VolumeSelector::PartitionGuid(guid) => volume.partition_guid.as_ref() == Some(guid),
+            VolumeSelector::PartitionLabel(label) => volume
+                .partition_label
+                .as_ref()
+                .is_some_and(|partition_label| partition_label.eq_ignore_ascii_case(label)),
+            VolumeSelector::Label(label) => volume.label.eq_ignore_ascii_case(label),
+        }
+    }
+}
+
+impl Drop for Root {
+    fn drop(&mut self) {
+        if self.owned {
+            close(self.handle).ok();
+        }
+    }
+}
+
+fn open_volume(device: efi::HANDLE) -> Result<*const efi::FILE_PROTOCOL, crate::Error> {
+    let file_system: *const efi::SIMPLE_FILE_SYSTEM_PROTOCOL =
+        crate::handle_protocol(device, &efi::SIMPLE_FILE_SYSTEM_PROTOCOL_GUID)?;
+
+    let mut root = null();
+    let status = unsafe { ((*file_system).open_volume)(file_system, &mut root) };
+    status.to_result("Failed to open volume")?;
+    Ok(root)
+}
+
+fn open_root(device: efi::HANDLE) -> Result<Root, crate::Error> {
+    if device == unsafe { BOOT_DEVICE } {
+        Ok(Root {
+            handle: get_boot_volume()?,
+            owned: false,
+        })
+    } else {
+        Ok(Root {
+            handle: open_volume(device)?,
+            owned: true,
+        })
+    }
+}
+
+fn open_ext2(device: efi::HANDLE) -> Result<Ext2, crate::Error> {
+    Ext2::open(BlockDevice::open(device)?)
+}
+
+// Returns the device handle and file system of the volume `path` names and
+// the rest of the path
+fn find_volume(path: &str) -> Result<(efi::HANDLE, FileSystem, &str), crate::Error> {
+    let (selector, qualifier, path) = split_volume(path)?;
+
+    let selector = match selector {
+        None => return Ok((unsafe { BOOT_DEVICE }, FileSystem::Firmware, path)),
+        Some(selector) => selector,
+    };
+
+    let volume = volumes()?
+        .into_iter()
+        .find(|volume| selector.matches(volume))
+        .ok_or_else(|| {
+            crate::error!(
+                efi::STATUS::NOT_FOUND,
+                "No volume matches \"{}\"",
+                qualifier
+            )
+        })?;
+
+    Ok((volume.handle, volume.file_system, path))
+}
+
+fn read_only_volume() -> crate::Error {
+    crate::Error::new(
+        efi::STATUS::WRITE_PROTECTED,
+        "The volume is write protected",
+    )
+}
+
+fn not_a_directory(path: &str) -> crate::Error {
+    crate::error!(
+        efi::STATUS::INVALID_PARAMETER,
+        "\"{}\" is not a directory",
+        path
+    )
+}
+
+// Splits the volume qualifier, if there is one, off the front of `path`
+fn split_volume(path: &str) -> Result<(Option<VolumeSelector<'_>>, &str, &str), crate::Error> {
+    let (qualifier, rest) = match path.split_once(':') {
+        Some((qualifier, rest)) if !qualifier.contains(|c| c == '\\' || c == '/') => {
+            (qualifier, rest)
+        }
+        _ => return Ok((None, "", path)),
+    };
+
+    let selector = match qualifier.split_once('=') {
+        Some(("part", guid)) => VolumeSelector::PartitionGuid(guid.parse()?),
+        Some(("partlabel", label)) => VolumeSelector::PartitionLabel(label),
+        Some(("label", label)) => VolumeSelector::Label(label),
+        _ => {
+            return Err(crate::error!(
+                efi::STATUS::INVALID_PARAMETER,
+                "Unknown volume qualifier \"{}\"",
+                qualifier
+            ))
+        }
     };
+
+    Ok((Some(selector), qualifier, rest))
+}
+
+// Only GPT partitions have a unique GUID and a label
+fn get_partition_info(device: efi::HANDLE) -> (Option<GUID>, Option<String>) {
+    let info: *const efi::PARTITION_INFO_PROTOCOL =
+        match crate::handle_protocol(device, &efi::PARTITION_INFO_PROTOCOL_GUID) {
+            Ok(info) => info,
+            Err(_) => return get_partition_info_from_disk(device),
+        };
+
+    let info = unsafe { core::ptr::read_unaligned(info) };
+    if info.partition_type != efi::PARTITION_TYPE_GPT {
+        return (None, None);
+    }
+
+    let entry = unsafe { info.info.gpt };
+    let name =
+        core::char::decode_utf16(entry.partition_name.iter().cloned().take_while(|c| *c != 0))
+            .map(|c| c.unwrap_or(core::char::REPLACEMENT_CHARACTER))
+            .collect();
+
+    (Some(entry.unique_partition_guid), Some(name))
+}
+
+// The partition info protocol is only on UEFI 2.7 and later. Before that
+// the GUID is in the device path's hard drive node and the label is only in
+// the disk's GPT.
+fn get_partition_info_from_disk(device: efi::HANDLE) -> (Option<GUID>, Option<String>) {
+    let path = match DevicePath::for_handle(device) {
+        Ok(path) => path,
+        Err(_) => return (None, None),
+    };
+
+    let guid = path.nodes().filter_map(|node| match node.parse() {
+        Node::HardDrive {
+            signature: PartitionSignature::Gpt(guid),
+            ..
+        } => Some(guid),
+        _ => None,
+    });
+    let guid = match guid.last() {
+        Some(guid) => guid,
+        None => return (None, None),
+    };
+
+    let name = crate::block::disks()
+        .ok()
+        .and_then(|disks| {
+            disks.into_iter().find(|disk| {
+                disk.device_path()
+                    .map(|disk_path| path.starts_with(&disk_path))
+                    .unwrap_or(false)
+            })
+        })
+        .and_then(|disk| Gpt::read(&disk).ok())
+        .and_then(|table| {
+            table
+                .partitions
+                .into_iter()
+                .find(|partition| partition.unique_guid == guid)
+        })
+        .map(|partition| partition.name);
+
+    (Some(guid), name)
+}
+
+pub(crate) fn boot_device() -> efi::HANDLE {
+    unsafe { BOOT_DEVICE }
+}
+
+fn get_boot_volume() -> Result<*const efi::FILE_PROTOCOL, crate::Error> {
+    match unsafe { BOOT_VOLUME } {
+        None => Err(crate::Error::new(
+            efi::STATUS::NOT_READY,
+            "Failed to open file",
+        )),
+        Some(boot_volume) => Ok(boot_volume),
+    }
+}
+
+fn convert_path(path: &str) -> Result<CString16, crate::Error> {
+    normalize_path(path)
+        .parse()
+        .with_context(format_args!("Invalid path \"{}\"", path))
+}
+
+fn get_file_info(handle: *const efi::FILE_PROTOCOL) -> Result<FileInfo, crate::Error> {
+    Ok(FileInfo::from_buffer(&get_file_info_buffer(handle)?))
+}
+
+fn get_file_info_buffer(handle: *const efi::FILE_PROTOCOL) -> Result<Vec<u64>, crate::Error> {
+    get_info_buffer(handle, &efi::FILE_INFO_ID)
+}
+
+fn get_info_buffer(
+    handle: *const efi::FILE_PROTOCOL,
+    information_type: &efi::GUID,
+) -> Result<Vec<u64>, crate::Error> {
+    let mut size: efi::UINTN = 0;
+    let status = unsafe { ((*handle).get_info)(handle, information_type, &mut size, null_mut()) };
     if status != efi::STATUS::BUFFER_TOO_SMALL {
         return Err(crate::Error::new(status, "Failed to get file info size"));
     }
 
-    let file_info: Vec<u8> = Vec::with_capacity(file_info_size);
+    // A u64 buffer keeps the info structure correctly aligned
+    let mut buffer: Vec<u64> = vec![0; size.div_ceil(core::mem::size_of::<u64>())];
     let status = unsafe {
         ((*handle).get_info)(
             handle,
-            &efi::FILE_INFO_ID,
-            &mut file_info_size,
-            file_info.as_ptr() as *mut efi::VOID,
+            information_type,
+            &mut size,
+            buffer.as_mut_ptr() as *mut efi::VOID,
         )
     };
-    if status != efi::STATUS::SUCCESS {
-        return Err(crate::Error::new(status, "Failed to get file info"));
-    }
+    status.to_result("Failed to get file info")?;
 
-    let mut file_size: efi::UINTN =
-        unsafe { (*(file_info.as_ptr() as *const efi::FILE_INFO)).file_size } as efi::UINTN;
-
-    let mut data = vec![0; file_size];
-
-    let status =
-        unsafe { ((*handle).read)(handle, &mut file_size, data.as_mut_ptr() as *mut efi::VOID) };
-    match status {
-        efi::STATUS::SUCCESS => Ok(data),
-        _ => Err(crate::Error::new(status, "Failed to read file")),
-    }
+    Ok(buffer)
 }
@@ -0,0 +1,407 @@
+use crate::{block::BlockDevice, config_table::GUID, crc32::crc32, efi, ResultExt};
+use alloc::{string::String, vec, vec::Vec};
+
+// The header is 92 bytes, which the struct pads to 96
+const MINIMUM_HEADER_SIZE: usize = 92;
+const MINIMUM_ENTRY_SIZE: usize = core::mem::size_of::<efi::PARTITION_ENTRY>();
+
+// Larger arrays than this are rejected rather than allocated
+const MAXIMUM_ENTRY_ARRAY_SIZE: usize = 0x100000;
+
+const UNUSED_ENTRY: GUID = GUID {
+    a: 0,
+    b: 0,
+    c: 0,
+    d: [0; 8],
+};
+
+pub struct Gpt {
+    pub disk_guid: GUID,
+    pub first_usable_lba: u64,
+    pub last_usable_lba: u64,
+    pub partitions: Vec<Partition>,
+    // Set when the primary table was damaged and the backup was used
+    pub used_backup: bool,
+}
+
+pub struct Partition {
+    // Entries are numbered from one, matching the device path's partition number
+    pub number: u32,
+    pub type_guid: GUID,
+    pub unique_guid: GUID,
+    pub name: String,
+    pub first_lba: u64,
+    pub last_lba: u64,
+    pub attributes: u64,
+}
+
+impl Gpt {
+    pub fn read(disk: &BlockDevice) -> Result<Self, crate::Error> {
+        let block_size = disk.block_size() as usize;
+        Gpt::parse(block_size, disk.last_block(), |offset, buffer| {
+            disk.read(offset, buffer)
+        })
+    }
+
+    // `read` fills a buffer from a byte offset on the disk, so this works on
+    // anything that looks like one, not only block devices
+    pub fn parse(
+        block_size: usize,
+        last_block: u64,
+        mut read: impl FnMut(u64, &mut [u8]) -> Result<(), crate::Error>,
+    ) -> Result<Self, crate::Error> {
+        match read_table(
+            block_size,
+            efi::PRIMARY_PARTITION_HEADER_LBA,
+            last_block,
+            &mut read,
+        ) {
+            Ok(table) => Ok(table),
+            Err(primary) => {
+                let mut table = read_table(block_size, last_block, last_block, &mut read)
+                    .map_err(|_| primary)?;
+                table.used_backup = true;
+                Ok(table)
+            }
+        }
+    }
+
+    pub fn find(&self, unique_guid: &GUID) -> Option<&Partition> {
+        self.partitions
+            .iter()
+            .find(|partition| partition.unique_guid == *unique_guid)
+    }
+}
+
+impl Partition {
+    pub fn block_count(&self) -> u64 {
+        self.last_lba - self.first_lba + 1
+    }
+
+    // Reads from the partition's raw contents, bounded to the partition
+    pub fn read(
+        &self,
+        disk: &BlockDevice,
+        offset: u64,
+        buffer: &mut [u8],
+    ) -> Result<(), crate::Error> {
+        let block_size = disk.block_size() as u64;
+        if offset + buffer.len() as u64 > self.block_count() * block_size {
+            return Err(crate::Error::new(
+                efi::STATUS::INVALID_PARAMETER,
+                "Read is past the end of the partition",
+            ));
+        }
+
+        disk.read(self.first_lba * block_size + offset, buffer)
+    }
+}
+
+fn read_table(
+    block_size: usize,
+    lba: u64,
+    last_block: u64,
+    read: &mut impl FnMut(u64, &mut [u8]) -> Result<(), crate::Error>,
+) -> Result<Gpt, crate::Error> {
+    if block_size < core::mem::size_of::<efi::PARTITION_TABLE_HEADER>() {
+        return Err(crate::Error::new(
+            efi::STATUS::UNSUPPORTED,
+            "Block size is too small for a GPT",
+        ));
+    }
+
+    let mut block = vec![0; block_size];
+    read(lba * block_size as u64, &mut block)?;
+    let header: efi::PARTITION_TABLE_HEADER =
+        unsafe { core::ptr::read_unaligned(block.as_ptr() as *const _) };
+
+    if header.header.signature != efi::PARTITION_TABLE_HEADER_SIGNATURE {
+        return Err(crate::Error::new(
+            efi::STATUS::NOT_FOUND,
+            "Disk does not have a GPT",
+        ));
+    }
+
+    let header_size = header.header.header_size as usize;
+    if header_size < MINIMUM_HEADER_SIZE || header_size > block_size {
+        return Err(crate::Error::new(
+            efi::STATUS::COMPROMISED_DATA,
+            "Invalid GPT header size",
+        ));
+    }
+
+    // The header's CRC is calculated with its own field zeroed
+    block[16..20].fill(0);
+    if crc32(&block[..header_size]) != header.header.crc32 {
+        return Err(crate::Error::new(
+            efi::STATUS::CRC_ERROR,
+            "GPT header CRC mismatch",
+        ));
+    }
+
+    if header.my_lba != lba {
+        return Err(crate::Error::new(
+            efi::STATUS::COMPROMISED_DATA,
+            "GPT header is in the wrong place",
+        ));
+    }
+
+    if header.first_usable_lba > header.last_usable_lba || header.last_usable_lba > last_block {
+        return Err(crate::Error::new(
+            efi::STATUS::COMPROMISED_DATA,
+            "Invalid GPT usable block range",
+        ));
+    }
+
+    let entry_size = header.size_of_partition_entry as usize;
+    let entry_count = header.number_of_partition_entries as usize;
+    let array_size = entry_size * entry_count;
+    if entry_size < MINIMUM_ENTRY_SIZE
+        || !entry_size.is_power_of_two()
+        || array_size > MAXIMUM_ENTRY_ARRAY_SIZE
+    {
+        return Err(crate::Error::new(
+            efi::STATUS::COMPROMISED_DATA,
+            "Invalid GPT partition entry array",
+        ));
+    }
+
+    let mut entries = vec![0; array_size];
+    read(header.partition_entry_lba * block_size as u64, &mut entries)
+        .context("Failed to read the GPT partition entries")?;
+    if crc32(&entries) != header.partition_entry_array_crc32 {
+        return Err(crate::Error::new(
+            efi::STATUS::CRC_ERROR,
+            "GPT partition entry array CRC mismatch",
+        ));
+    }
+
+    let mut partitions = Vec::new();
+    for (i, entry) in entries.chunks_exact(entry_size).enumerate() {
+        let entry: efi::PARTITION_ENTRY =
+            unsafe { core::ptr::read_unaligned(entry.as_ptr() as *const _) };
+        if entry.partition_type_guid == UNUSED_ENTRY {
+            continue;
+        }
+
+        if entry.ending_lba < entry.starting_lba {
+            return Err(crate::error!(
+                efi::STATUS::COMPROMISED_DATA,
+                "GPT partition {} ends before it starts",
+                i + 1
+            ));
+        }
+
+        if entry.starting_lba < header.first_usable_lba || entry.ending_lba > header.last_usable_lba
+        {
+            return Err(crate::error!(
+                efi::STATUS::COMPROMISED_DATA,
+                "GPT partition {} is outside the usable blocks",
+                i + 1
+            ));
+        }
+
+        partitions.push(Partition {
+            number: i as u32 + 1,
+            type_guid: entry.partition_type_guid,
+            unique_guid: entry.unique_partition_guid,
+            name: core::char::decode_utf16(
+                entry.partition_name.iter().cloned().take_while(|c| *c != 0),
+            )
+            .map(|c| c.unwrap_or(core::char::REPLACEMENT_CHARACTER))
+            .collect(),
+            first_lba: entry.starting_lba,
+            last_lba: entry.ending_lba,
+            attributes: entry.attributes,
+        });
+    }
+
+    Ok(Gpt {
+        disk_guid: header.disk_guid,
+        first_usable_lba: header.first_usable_lba,
+        last_usable_lba: header.last_usable_lba,
+        partitions,
+        used_backup: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK_SIZE: usize = 512;
+    const LAST_BLOCK: u64 = 63;
+    const ENTRY_COUNT: usize = 4;
+    const ENTRY_SIZE: usize = 128;
+
+    const DISK_GUID: &str = "6B2B6E6A-3C5D-4B0E-9E31-0D1F2B3C4D5E";
+    const TYPE_GUID: &str = "4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709";
+    const UNIQUE_GUID: &str = "A1B2C3D4-E5F6-4789-8ABC-DEF012345678";
+
+    // The entry array takes one block, so the backup entries are in the
+    // second to last block and the last usable block is the one before
+    const FIRST_USABLE_LBA: u64 = 34;
+    const LAST_USABLE_LBA: u64 = LAST_BLOCK - 2;
+
+    // A 64 block disk with one partition and both copies of the table
+    fn disk_image() -> Vec<u8> {
+        let mut image = vec![0; BLOCK_SIZE * (LAST_BLOCK as usize + 1)];
+
+        let mut entries = vec![0; ENTRY_COUNT * ENTRY_SIZE];
+        entries[0..16].copy_from_slice(&guid_bytes(TYPE_GUID));
+        entries[16..32].copy_from_slice(&guid_bytes(UNIQUE_GUID));
+        entries[32..40].copy_from_slice(&FIRST_USABLE_LBA.to_le_bytes());
+        entries[40..48].copy_from_slice(&59u64.to_le_bytes());
+        for (i, c) in "root".encode_utf16().enumerate() {
+            entries[56 + i * 2..58 + i * 2].copy_from_slice(&c.to_le_bytes());
+        }
+
+        write_table(&mut image, 1, LAST_BLOCK, 2, &entries);
+        write_table(&mut image, LAST_BLOCK, 1, LAST_BLOCK - 1, &entries);
+        image
+    }
+
+    fn write_table(
+        image: &mut [u8],
+        lba: u64,
+        alternate_lba: u64,
+        entries_lba: u64,
+        entries: &[u8],
+    ) {
+        let entries_offset = entries_lba as usize * BLOCK_SIZE;
+        image[entries_offset..entries_offset + entries.len()].copy_from_slice(entries);
+
+        let mut header = [0; 92];
+        header[0..8].copy_from_slice(b"EFI PART");
+        header[8..12].copy_from_slice(&0x00010000u32.to_le_bytes());
+        header[12..16].copy_from_slice(&92u32.to_le_bytes());
+        header[24..32].copy_from_slice(&lba.to_le_bytes());
+        header[32..40].copy_from_slice(&alternate_lba.to_le_bytes());
+        header[40..48].copy_from_slice(&FIRST_USABLE_LBA.to_le_bytes());
+        header[48..56].copy_from_slice(&LAST_USABLE_LBA.to_le_bytes());
+        header[56..72].copy_from_slice(&guid_bytes(DISK_GUID));
+        header[72..80].copy_from_slice(&entries_lba.to_le_bytes());
+        header[80..84].copy_from_slice(&(ENTRY_COUNT as u32).to_le_bytes());
+        header[84..88].copy_from_slice(&(ENTRY_SIZE as u32).to_le_bytes());
+        header[88..92].copy_from_slice(&crc32(entries).to_le_bytes());
+        let crc = crc32(&header);
+        header[16..20].copy_from_slice(&crc.to_le_bytes());
+
+        let offset = lba as usize * BLOCK_SIZE;
+        image[offset..offset + header.len()].copy_from_slice(&header);
+    }
+
+    fn guid_bytes(guid: &str) -> [u8; 16] {
+        let guid: GUID = guid.parse().unwrap();
+        let mut bytes = [0; 16];
+        bytes[0..4].copy_from_slice(&guid.a.to_le_bytes());
+        bytes[4..6].copy_from_slice(&guid.b.to_le_bytes());
+        bytes[6..8].copy_from_slice(&guid.c.to_le_bytes());
+        bytes[8..16].copy_from_slice(&guid.d);
+        bytes
+    }
+
+    fn parse(image: &[u8]) -> Result<Gpt, crate::Error> {
+        Gpt::parse(BLOCK_SIZE, LAST_BLOCK, |offset, buffer| {
+            let offset = offset as usize;
+            match image.get(offset..offset + buffer.len()) {
+                Some(data) => {
+                    buffer.copy_from_slice(data);
+                    Ok(())
+                }
+                None => Err(crate::Error::new(
+                    efi::STATUS::INVALID_PARAMETER,
+                    "Read is past the end of the image",
+                )),
+            }
+        })
+    }
+
+    #[test]
+    fn parses_primary_table() {
+        let gpt = parse(&disk_image()).unwrap();
+
+        assert!(!gpt.used_backup);
+        assert_eq!(gpt.disk_guid, DISK_GUID.parse().unwrap());
+        assert_eq!(gpt.first_usable_lba, FIRST_USABLE_LBA);
+        assert_eq!(gpt.last_usable_lba, LAST_USABLE_LBA);
+        assert_eq!(gpt.partitions.len(), 1);
+
+        let partition = gpt.find(&UNIQUE_GUID.parse().unwrap()).unwrap();
+        assert_eq!(partition.number, 1);
+        assert_eq!(partition.type_guid, TYPE_GUID.parse().unwrap());
+        assert_eq!(partition.name, "root");
+        assert_eq!(partition.first_lba, FIRST_USABLE_LBA);
+        assert_eq!(partition.block_count(), 26);
+        assert!(partition.last_lba <= gpt.last_usable_lba);
+    }
+
+    #[test]
+    fn falls_back_to_backup_table() {
+        let mut image = disk_image();
+        image[BLOCK_SIZE..BLOCK_SIZE * 2].fill(0);
+
+        let gpt = parse(&image).unwrap();
+        assert!(gpt.used_backup);
+        assert_eq!(gpt.partitions.len(), 1);
+        assert_eq!(gpt.partitions[0].unique_guid, UNIQUE_GUID.parse().unwrap());
+    }
+
+    #[test]
+    fn rejects_bad_header_crc() {
+        let mut image = disk_image();
+        // Change the first usable LBA in both headers without fixing their CRCs
+        image[BLOCK_SIZE + 40] ^= 1;
+        image[LAST_BLOCK as usize * BLOCK_SIZE + 40] ^= 1;
+
+        let err = parse(&image).err().unwrap();
+        assert_eq!(err.status(), efi::STATUS::CRC_ERROR);
+        assert_eq!(err.message(), "GPT header CRC mismatch");
+    }
+
+    #[test]
+    fn rejects_bad_entry_array_crc() {
+        let mut image = disk_image();
+        image[BLOCK_SIZE * 2 + 32] ^= 1;
+        image[(LAST_BLOCK as usize - 1) * BLOCK_SIZE + 32] ^= 1;
+
+        let err = parse(&image).err().unwrap();
+        assert_eq!(err.status(), efi::STATUS::CRC_ERROR);
+        assert_eq!(err.message(), "GPT partition entry array CRC mismatch");
+    }
+
+    #[test]
+    fn rejects_inverted_partition() {
+        let mut image = disk_image();
+        let mut entries = image[BLOCK_SIZE * 2..BLOCK_SIZE * 2 + ENTRY_COUNT * ENTRY_SIZE].to_vec();
+        entries[40..48].copy_from_slice(&10u64.to_le_bytes());
+        write_table(&mut image, 1, LAST_BLOCK, 2, &entries);
+        write_table(&mut image, LAST_BLOCK, 1, LAST_BLOCK - 1, &entries);
+
+        let err = parse(&image).err().unwrap();
+        assert_eq!(err.status(), efi::STATUS::COMPROMISED_DATA);
+    }
+
+    #[test]
+    fn rejects_partition_outside_usable_blocks() {
+        for (first, last) in [
+            (FIRST_USABLE_LBA - 1, 59),
+            (FIRST_USABLE_LBA, LAST_USABLE_LBA + 1),
+        ] {
+            let mut image = disk_image();
+            let mut entries =
+                image[BLOCK_SIZE * 2..BLOCK_SIZE * 2 + ENTRY_COUNT * ENTRY_SIZE].to_vec();
+            entries[32..40].copy_from_slice(&first.to_le_bytes());
+            entries[40..48].copy_from_slice(&last.to_le_bytes());
+            write_table(&mut image, 1, LAST_BLOCK, 2, &entries);
+            write_table(&mut image, LAST_BLOCK, 1, LAST_BLOCK - 1, &entries);
+
+            let err = parse(&image).err().unwrap();
+            assert_eq!(err.status(), efi::STATUS::COMPROMISED_DATA);
+            assert_eq!(
+                err.message(),
+                "GPT partition 1 is outside the usable blocks"
+            );
+        }
+    }
+}
@@ -1,6 +1,9 @@
+use alloc::vec::Vec;
 use core::ptr::null;
 
-use crate::efi;
+use crate::{edid::Edid, efi};
+
+pub type BltPixel = efi::GRAPHICS_OUTPUT_BLT_PIXEL;
 
 #[repr(C)]
 pub struct GraphicsMode {
@@ -13,15 +16,225 @@ pub struct GraphicsMode {
     pub pixels_per_scanline: u32,
     pub framebuffer: *mut u32,
     pub framebuffer_size: usize,
+    pub edid: Edid,
+    pub primary: bool,
+}
+
+#[repr(C)]
+pub struct GraphicsOutputs {
+    pub count: usize,
+    pub modes: *const GraphicsMode,
+}
+
+// Holding on to an output lets it be drawn to without allocating, which
+// matters once the memory map has been read
+#[derive(Clone, Copy)]
+pub struct GraphicsOutput {
+    handle: efi::HANDLE,
+    gop: *const efi::GRAPHICS_OUTPUT_PROTOCOL,
 }
 
 static mut LOCATE_PROTOCOL: Option<efi::LOCATE_PROTOCOL> = None;
+static mut PRIMARY_OUTPUT: Option<usize> = None;
+// Found on first use so drawing doesn't enumerate every output each time
+static mut PRIMARY: Option<GraphicsOutput> = None;
 
 pub fn initialize(boot_services: &efi::BOOT_SERVICES) {
     unsafe { LOCATE_PROTOCOL = Some(boot_services.locate_protocol) };
 }
 
-pub fn get_info() -> Result<GraphicsMode, crate::Error> {
+pub fn get_info() -> Result<Vec<GraphicsMode>, crate::Error> {
+    let outputs = get_outputs()?;
+    if outputs.is_empty() {
+        return Err(crate::Error::new(
+            efi::STATUS::NOT_FOUND,
+            "Failed to get graphics information",
+        ));
+    }
+
+    let primary = get_primary(&outputs)?;
+
+    let mut modes = Vec::with_capacity(outputs.len());
+    for (i, output) in outputs.iter().enumerate() {
+        let mode = unsafe { &*((*output.gop).mode) };
+        let info = unsafe { &*(mode.info) };
+
+        modes.push(GraphicsMode {
+            horizontal_resolution: info.horizontal_resolution,
+            vertical_resolution: info.vertical_resolution,
+            pixel_format: info.pixel_format as u32,
+            red_mask: info.pixel_information.red_mask,
+            green_mask: info.pixel_information.green_mask,
+            blue_mask: info.pixel_information.blue_mask,
+            pixels_per_scanline: info.pixels_per_scanline,
+            framebuffer: mode.framebuffer_base as *mut u32,
+            framebuffer_size: mode.framebuffer_size,
+            edid: get_edid(output.handle),
+            primary: i == primary,
+        });
+    }
+
+    Ok(modes)
+}
+
+pub fn set_primary(index: usize) -> Result<(), crate::Error> {
+    let outputs = get_outputs()?;
+    let output = match outputs.get(index) {
+        Some(output) => output,
+        None => {
+            return Err(crate::Error::new(
+                efi::STATUS::INVALID_PARAMETER,
+                "Invalid graphics output",
+            ))
+        }
+    };
+
+    // Move the console to the text output on the same handle, if there is one
+    if let Ok(text_output) =
+        crate::handle_protocol(output.handle, &efi::SIMPLE_TEXT_OUTPUT_PROTOCOL_GUID)
+    {
+        crate::console::set_output(text_output)?;
+    }
+
+    unsafe {
+        PRIMARY_OUTPUT = Some(index);
+        PRIMARY = Some(*output);
+    }
+
+    Ok(())
+}
+
+pub fn get_resolution() -> Result<(u32, u32), crate::Error> {
+    Ok(GraphicsOutput::primary()?.resolution())
+}
+
+pub fn fill_rect(
+    color: BltPixel,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+) -> Result<(), crate::Error> {
+    GraphicsOutput::primary()?.fill_rect(color, x, y, width, height)
+}
+
+pub fn draw_image(
+    pixels: &[BltPixel],
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+) -> Result<(), crate::Error> {
+    GraphicsOutput::primary()?.draw_image(pixels, x, y, width, height)
+}
+
+impl GraphicsOutput {
+    pub fn primary() -> Result<Self, crate::Error> {
+        if let Some(output) = unsafe { PRIMARY } {
+            return Ok(output);
+        }
+
+        let output = get_primary_output()?;
+        unsafe { PRIMARY = Some(output) };
+        Ok(output)
+    }
+
+    pub fn resolution(&self) -> (u32, u32) {
+        let info = unsafe { &*((*(*self.gop).mode).info) };
+        (info.horizontal_resolution, info.vertical_resolution)
+    }
+
+    pub fn fill_rect(
+        &self,
+        color: BltPixel,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<(), crate::Error> {
+        let mut color = color;
+        let status = unsafe {
+            ((*self.gop).blt)(
+                self.gop,
+                &mut color,
+                efi::GRAPHICS_OUTPUT_BLT_OPERATION::BltVideoFill,
+                0,
+                0,
+                x,
+                y,
+                width,
+                height,
+                0,
+            )
+        };
+        status.to_result("Failed to fill rectangle")
+    }
+
+    pub fn draw_image(
+        &self,
+        pixels: &[BltPixel],
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<(), crate::Error> {
+        if pixels.len() < width * height {
+            return Err(crate::Error::new(
+                efi::STATUS::BAD_BUFFER_SIZE,
+                "Image buffer is too small",
+            ));
+        }
+
+        let status = unsafe {
+            ((*self.gop).blt)(
+                self.gop,
+                pixels.as_ptr() as *mut _,
+                efi::GRAPHICS_OUTPUT_BLT_OPERATION::BltBufferToVideo,
+                0,
+                0,
+                x,
+                y,
+                width,
+                height,
+                0,
+            )
+        };
+        status.to_result("Failed to draw image")
+    }
+}
+
+fn get_primary_output() -> Result<GraphicsOutput, crate::Error> {
+    let mut outputs = get_outputs()?;
+    if outputs.is_empty() {
+        return Err(crate::Error::new(
+            efi::STATUS::NOT_FOUND,
+            "No graphics output",
+        ));
+    }
+
+    let primary = get_primary(&outputs)?;
+    Ok(outputs.swap_remove(primary))
+}
+
+fn get_outputs() -> Result<Vec<GraphicsOutput>, crate::Error> {
+    let handles = crate::locate_handles(&efi::GRAPHICS_OUTPUT_PROTOCOL_GUID)?;
+
+    let mut outputs = Vec::with_capacity(handles.len());
+    for handle in handles {
+        let gop = crate::handle_protocol(handle, &efi::GRAPHICS_OUTPUT_PROTOCOL_GUID)?;
+        outputs.push(GraphicsOutput { handle, gop });
+    }
+
+    Ok(outputs)
+}
+
+fn get_primary(outputs: &[GraphicsOutput]) -> Result<usize, crate::Error> {
+    // Outputs can go away after set_primary, e.g. when a display is unplugged
+    if let Some(primary) = unsafe { PRIMARY_OUTPUT } {
+        return Ok(if primary < outputs.len() { primary } else { 0 });
+    }
+
+    // Default to the output the firmware hands out first
     let mut gop: *const efi::GRAPHICS_OUTPUT_PROTOCOL = null();
     let status = unsafe {
         match LOCATE_PROTOCOL {
@@ -38,25 +251,34 @@ pub fn get_info() -> Result<GraphicsMode, crate::Error> {
             ),
         }
     };
-    if status != efi::STATUS::SUCCESS {
-        return Err(crate::Error::new(
-            status,
-            "Failed to get graphics information",
-        ));
+    status.to_result("Failed to get graphics information")?;
+
+    Ok(outputs
+        .iter()
+        .position(|output| output.gop == gop)
+        .unwrap_or(0))
+}
+
+fn get_edid(handle: efi::HANDLE) -> Edid {
+    // Prefer the active EDID as it may have been overridden by the platform
+    for guid in &[
+        efi::EDID_ACTIVE_PROTOCOL_GUID,
+        efi::EDID_DISCOVERED_PROTOCOL_GUID,
+    ] {
+        let edid: *const efi::EDID_PROTOCOL = match crate::handle_protocol(handle, guid) {
+            Ok(edid) => edid,
+            Err(_) => continue,
+        };
+
+        let (size, data) = unsafe { ((*edid).size_of_edid as usize, (*edid).edid) };
+        if size == 0 || data.is_null() {
+            continue;
+        }
+
+        if let Ok(edid) = Edid::parse(unsafe { core::slice::from_raw_parts(data, size) }) {
+            return edid;
+        }
     }
 
-    let mode = unsafe { &*((*gop).mode) };
-    let info = unsafe { &*(mode.info) };
-
-    Ok(GraphicsMode {
-        horizontal_resolution: info.horizontal_resolution,
-        vertical_resolution: info.vertical_resolution,
-        pixel_format: info.pixel_format as u32,
-        red_mask: info.pixel_information.red_mask,
-        green_mask: info.pixel_information.green_mask,
-        blue_mask: info.pixel_information.blue_mask,
-        pixels_per_scanline: info.pixels_per_scanline,
-        framebuffer: mode.framebuffer_base as *mut u32,
-        framebuffer_size: mode.framebuffer_size,
-    })
+    Edid::empty()
 }
@@ -0,0 +1,152 @@
+use crate::{efi, string::CString16, ResultExt};
+use core::ptr::null;
+
+// An EFI application loaded from a file, unloaded on drop unless it has run
+pub struct Image {
+    handle: efi::HANDLE,
+    started: bool,
+    // Kept alive here as the image only holds a pointer to them
+    load_options: Option<CString16>,
+}
+
+static mut LOAD_IMAGE: Option<efi::IMAGE_LOAD> = None;
+static mut START_IMAGE: Option<efi::IMAGE_START> = None;
+static mut UNLOAD_IMAGE: Option<efi::IMAGE_UNLOAD> = None;
+
+pub fn initialize(boot_services: &efi::BOOT_SERVICES) {
+    unsafe {
+        LOAD_IMAGE = Some(boot_services.load_image);
+        START_IMAGE = Some(boot_services.start_image);
+        UNLOAD_IMAGE = Some(boot_services.unload_image);
+    }
+}
+
+impl Image {
+    // Accepts any path `file::load_file` does, including other volumes
+    pub fn load(path: &str) -> Result<Self, crate::Error> {
+        let load_image = match unsafe { LOAD_IMAGE } {
+            None => return Err(not_ready()),
+            Some(load_image) => load_image,
+        };
+
+        let data = crate::file::load_file(path)?;
+
+        // The device path is only informational, so images on volumes the
+        // firmware can't describe are still loaded
+        let device_path = crate::file::device_path(path).ok();
+        let device_path_ptr = device_path.as_ref().map_or(null(), |path| path.as_ptr());
+
+        let mut handle = null();
+        let status = unsafe {
+            load_image(
+                efi::FALSE,
+                crate::IMAGE_HANDLE,
+                device_path_ptr,
+                data.as_ptr() as *const efi::VOID,
+                data.len(),
+                &mut handle,
+            )
+        };
+
+        match status {
+            // The image is loaded but may not be started, so it must still be unloaded
+            efi::STATUS::SECURITY_VIOLATION => {
+                unload(handle).ok();
+                Err(crate::Error::new(
+                    status,
+                    "Image was rejected by Secure Boot",
+                ))
+            }
+            efi::STATUS::UNSUPPORTED | efi::STATUS::LOAD_ERROR => Err(crate::Error::new(
+                status,
+                "Image is not an EFI application for this machine",
+            )),
+            _ => status.to_result("Failed to load image"),
+        }
+        .with_context(format_args!("Failed to load \"{}\"", path))?;
+
+        Ok(Image {
+            handle,
+            started: false,
+            load_options: None,
+        })
+    }
+
+    pub fn set_load_options(&mut self, options: &str) -> Result<(), crate::Error> {
+        let options: CString16 = options.parse()?;
+
+        let loaded_image: *const efi::LOADED_IMAGE_PROTOCOL =
+            crate::handle_protocol(self.handle, &efi::LOADED_IMAGE_PROTOCOL_GUID)?;
+        let loaded_image = loaded_image as *mut efi::LOADED_IMAGE_PROTOCOL;
+
+        let slice = options.as_slice_with_nul();
+        unsafe {
+            (*loaded_image).load_options = slice.as_ptr() as *const efi::VOID;
+            (*loaded_image).load_options_size = core::mem::size_of_val(slice) as u32;
+        }
+
+        self.load_options = Some(options);
+        Ok(())
+    }
+
+    // Runs the image until it exits, which is an error if it exits with one
+    pub fn start(mut self) -> Result<(), crate::Error> {
+        let start_image = match unsafe { START_IMAGE } {
+            None => return Err(not_ready()),
+            Some(start_image) => start_image,
+        };
+
+        let mut exit_data_size = 0;
+        let mut exit_data = null();
+        let status = unsafe { start_image(self.handle, &mut exit_data_size, &mut exit_data) };
+
+        // StartImage itself only fails for a bad handle or a security policy
+        // violation, anything else is the image's exit status. Exited images
+        // are unloaded by the firmware, ones that never ran are unloaded on drop.
+        self.started = !exit_data.is_null()
+            || (status != efi::STATUS::INVALID_PARAMETER
+                && status != efi::STATUS::SECURITY_VIOLATION);
+
+        // Applications may describe why they failed in the exit data
+        let mut message = alloc::string::String::new();
+        if !exit_data.is_null() {
+            message = unsafe { crate::string::CStr16::from_ptr(exit_data) }.to_string_lossy();
+            crate::memory::free_pool(exit_data as *const core::ffi::c_void).ok();
+        }
+
+        if !self.started {
+            return Err(crate::Error::new(status, "Failed to start image"));
+        }
+
+        if status.is_error() {
+            if message.is_empty() {
+                return Err(crate::Error::new(status, "Image exited with an error"));
+            }
+            return Err(crate::error!(status, "Image exited: {}", message));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        if !self.started {
+            unload(self.handle).ok();
+        }
+    }
+}
+
+fn unload(handle: efi::HANDLE) -> Result<(), crate::Error> {
+    match unsafe { UNLOAD_IMAGE } {
+        None => Err(not_ready()),
+        Some(unload_image) => {
+            let status = unsafe { unload_image(handle) };
+            status.to_result("Failed to unload image")
+        }
+    }
+}
+
+fn not_ready() -> crate::Error {
+    crate::Error::new(efi::STATUS::NOT_READY, "Image services are not ready")
+}
@@ -1,58 +1,119 @@
-#![no_std]
-#![feature(alloc_error_handler)]
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), feature(alloc_error_handler))]
 
-use core::{ffi::c_void, ptr::null};
+use alloc::{string::String, vec::Vec};
+use core::{ffi::c_void, fmt, mem::size_of, ptr::null};
 
+pub mod acpi;
+pub mod block;
 pub mod config_table;
 pub mod console;
+mod crc32;
+pub mod device_path;
+pub mod edid;
 mod efi;
+mod error;
+mod ext2;
+pub mod fdt;
 pub mod file;
+pub mod gpt;
 pub mod graphics;
+pub mod image;
 pub mod memory;
+pub mod smbios;
+pub mod string;
 
 extern crate alloc;
 
-pub type Status = efi::STATUS;
+pub use error::{Error, ResultExt};
 
-pub struct Error {
-    status: Status,
-    message: &'static str,
-}
+pub type Status = efi::STATUS;
 
 static mut IMAGE_HANDLE: *const efi::VOID = null();
 static mut EXIT_BOOT_SERVICES: Option<efi::EXIT_BOOT_SERVICES> = None;
+static mut HANDLE_PROTOCOL: Option<efi::HANDLE_PROTOCOL> = None;
+static mut LOCATE_HANDLE_BUFFER: Option<efi::LOCATE_HANDLE_BUFFER> = None;
+static mut FIRMWARE_VENDOR: *const efi::CHAR16 = null();
+static mut FIRMWARE_REVISION: u32 = 0;
+static mut SPECIFICATION_REVISION: u32 = 0;
+static mut BOOT_SERVICES_EXITED: bool = false;
+
+// Older firmware lacks services the library relies on, like LocateHandleBuffer
+const MINIMUM_REVISION: u32 = efi::SYSTEM_TABLE_REVISION_1_10;
+// Anything larger is assumed to be a bad header rather than summed
+const MAXIMUM_HEADER_SIZE: usize = 0x1000;
+
+// A UEFI specification revision, with the major version in the top 16 bits
+// and the minor version times ten in the bottom 16
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Revision(pub u32);
 
 pub fn initialize(
     system_table: *const c_void,
     image_handle: *const c_void,
     entry: fn() -> Result<(), Error>,
 ) -> Result<(), Error> {
-    let system_table = from_pointer(system_table as *const efi::SYSTEM_TABLE);
+    // Nothing in a table is used until its header checks out
+    let system_table = system_table as *const efi::SYSTEM_TABLE;
+    let revision = unsafe {
+        validate_table(
+            system_table as *const efi::TABLE_HEADER,
+            efi::SYSTEM_TABLE_SIGNATURE,
+            |_| size_of::<efi::SYSTEM_TABLE>(),
+            "System Table",
+        )?
+    };
+    let system_table = from_pointer(system_table);
+
+    // The console doesn't allocate, so it can report problems with boot services
+    console::initialize(system_table)?;
+
+    unsafe {
+        validate_table(
+            system_table.boot_services as *const efi::TABLE_HEADER,
+            efi::BOOT_SERVICES_SIGNATURE,
+            // CreateEventEx, the last service, was added in 2.0
+            |revision| {
+                if revision >= efi::SYSTEM_TABLE_REVISION_2_00 {
+                    size_of::<efi::BOOT_SERVICES>()
+                } else {
+                    size_of::<efi::BOOT_SERVICES>() - size_of::<*const efi::VOID>()
+                }
+            },
+            "Boot Services",
+        )?
+    };
     let boot_services = from_pointer(system_table.boot_services);
 
+    unsafe {
+        FIRMWARE_VENDOR = system_table.firmware_vendor;
+        FIRMWARE_REVISION = system_table.firmware_revision;
+        SPECIFICATION_REVISION = revision;
+    }
+
     // Disable the watchdog timer
     let status = unsafe { (boot_services.set_watchdog_timer)(0, 0, 0, null()) };
-    if status != Status::SUCCESS {
-        return Err(Error::new(status, "Failed to set watchdog timer"));
-    }
+    status.to_result("Failed to set watchdog timer")?;
 
     unsafe {
         IMAGE_HANDLE = image_handle;
         EXIT_BOOT_SERVICES = Some(boot_services.exit_boot_services);
+        HANDLE_PROTOCOL = Some(boot_services.handle_protocol);
+        LOCATE_HANDLE_BUFFER = Some(boot_services.locate_handle_buffer);
     }
 
     // Initialize the memory
     memory::initialize(boot_services);
 
-    // Initialize the console
-    console::initialize(system_table)?;
-
     // Initialize the file interface
     file::initialize(boot_services, image_handle)?;
 
     // Initialize graphics info
     graphics::initialize(boot_services);
 
+    // Initialize image services
+    image::initialize(boot_services);
+
     // Initialize configuration tables
     config_table::initialize(system_table);
 
@@ -60,6 +121,63 @@ pub fn initialize(
     entry()
 }
 
+pub fn firmware_vendor() -> String {
+    let vendor = unsafe { FIRMWARE_VENDOR };
+    if vendor.is_null() {
+        return String::new();
+    }
+
+    unsafe { string::CStr16::from_ptr(vendor) }.to_string_lossy()
+}
+
+// Vendor specific, so only meaningful alongside the vendor
+pub fn firmware_revision() -> u32 {
+    unsafe { FIRMWARE_REVISION }
+}
+
+pub fn specification_revision() -> Revision {
+    Revision(unsafe { SPECIFICATION_REVISION })
+}
+
+// Fails clearly for features newer than the firmware, rather than leaving
+// them to show up as missing protocols or tables
+pub(crate) fn require_revision(revision: u32, feature: &str) -> Result<(), Error> {
+    let firmware = specification_revision();
+    if firmware < Revision(revision) {
+        return Err(error!(
+            efi::STATUS::UNSUPPORTED,
+            "{} requires UEFI {}, firmware is {}",
+            feature,
+            Revision(revision),
+            firmware
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn get_load_options() -> Result<String, Error> {
+    let loaded_image: *const efi::LOADED_IMAGE_PROTOCOL =
+        handle_protocol(unsafe { IMAGE_HANDLE }, &efi::LOADED_IMAGE_PROTOCOL_GUID)?;
+
+    let (size, options) = unsafe {
+        (
+            (*loaded_image).load_options_size as usize,
+            (*loaded_image).load_options as *const efi::CHAR16,
+        )
+    };
+    if size == 0 || options.is_null() {
+        return Ok(String::new());
+    }
+
+    let options = unsafe { core::slice::from_raw_parts(options, size / 2) };
+    Ok(
+        core::char::decode_utf16(options.iter().cloned().take_while(|c| *c != 0))
+            .map(|c| c.unwrap_or(core::char::REPLACEMENT_CHARACTER))
+            .collect(),
+    )
+}
+
 pub fn exit_boot_services(map_key: usize) -> Result<(), Error> {
     unsafe {
         match EXIT_BOOT_SERVICES {
@@ -68,47 +186,148 @@ pub fn exit_boot_services(map_key: usize) -> Result<(), Error> {
                 "Failed to exit boot services",
             )),
             Some(exit_boot_services) => {
+                // Set first as a failed call may still have torn down some services
+                BOOT_SERVICES_EXITED = true;
                 let status = exit_boot_services(IMAGE_HANDLE, map_key);
-                match status {
-                    efi::STATUS::SUCCESS => Ok(()),
-                    _ => Err(Error::new(status, "Failed to exit boot services")),
-                }
+                status.to_result("Failed to exit boot services")
             }
         }
     }
 }
 
-fn from_pointer<T>(ptr: *const T) -> &'static T {
-    unsafe { &*ptr }
+// Nothing that calls into the firmware is safe once this is true
+pub fn boot_services_exited() -> bool {
+    unsafe { BOOT_SERVICES_EXITED }
 }
 
-fn _from_pointer_mut<T>(ptr: *mut T) -> &'static mut T {
-    unsafe { &mut *ptr }
-}
-
-impl Error {
-    pub fn new(status: Status, message: &'static str) -> Self {
-        Error {
-            status: status,
-            message: message,
+pub(crate) fn locate_handles(protocol: &efi::GUID) -> Result<Vec<efi::HANDLE>, Error> {
+    let locate_handle_buffer = match unsafe { LOCATE_HANDLE_BUFFER } {
+        None => {
+            return Err(Error::new(
+                efi::STATUS::NOT_READY,
+                "Failed to locate handles",
+            ))
         }
+        Some(locate_handle_buffer) => locate_handle_buffer,
+    };
+
+    let mut no_handles = 0;
+    let mut buffer: *const efi::HANDLE = null();
+    let status = unsafe {
+        locate_handle_buffer(
+            efi::LOCATE_SEARCH_TYPE::ByProtocol,
+            protocol,
+            null(),
+            &mut no_handles,
+            &mut buffer,
+        )
+    };
+    if status == efi::STATUS::NOT_FOUND {
+        return Ok(Vec::new());
     }
+    status.to_result("Failed to locate handles")?;
+
+    let handles = unsafe { core::slice::from_raw_parts(buffer, no_handles) }.to_vec();
+    memory::free_pool(buffer as *const c_void)?;
+
+    Ok(handles)
 }
 
-impl core::fmt::Display for Error {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "{}: {}", self.message, self.status)
-    }
+pub(crate) fn handle_protocol<T>(
+    handle: efi::HANDLE,
+    protocol: &efi::GUID,
+) -> Result<*const T, Error> {
+    let handle_protocol = match unsafe { HANDLE_PROTOCOL } {
+        None => return Err(Error::new(efi::STATUS::NOT_READY, "Failed to get protocol")),
+        Some(handle_protocol) => handle_protocol,
+    };
+
+    let mut interface: *const T = null();
+    let status = unsafe {
+        handle_protocol(
+            handle,
+            protocol,
+            &mut interface as *mut *const _ as *mut *const efi::VOID,
+        )
+    };
+    status.to_result("Failed to get protocol")?;
+    Ok(interface)
 }
 
-impl From<Error> for usize {
-    fn from(error: Error) -> Self {
-        error.status as usize
+// Checks the signature, revision, size and CRC of a table header. The CRC
+// covers `header_size` bytes with the CRC field itself zeroed.
+unsafe fn validate_table(
+    table: *const efi::TABLE_HEADER,
+    signature: u64,
+    minimum_size: impl Fn(u32) -> usize,
+    name: &str,
+) -> Result<u32, Error> {
+    if table.is_null() {
+        return Err(error!(efi::STATUS::LOAD_ERROR, "{} is missing", name));
+    }
+
+    let header = &*table;
+    if header.signature != signature {
+        return Err(error!(
+            efi::STATUS::INCOMPATIBLE_VERSION,
+            "Invalid {} signature {:#x}", name, header.signature
+        ));
+    }
+
+    if header.revision < MINIMUM_REVISION {
+        return Err(error!(
+            efi::STATUS::INCOMPATIBLE_VERSION,
+            "{} revision {} is older than {}",
+            name,
+            Revision(header.revision),
+            Revision(MINIMUM_REVISION)
+        ));
     }
+
+    let header_size = header.header_size as usize;
+    if header_size < minimum_size(header.revision) || header_size > MAXIMUM_HEADER_SIZE {
+        return Err(error!(
+            efi::STATUS::INCOMPATIBLE_VERSION,
+            "Invalid {} size {}", name, header_size
+        ));
+    }
+
+    let data = core::slice::from_raw_parts(table as *const u8, header_size);
+    let mut crc = crc32::update(!0, &data[..16]);
+    crc = crc32::update(crc, &[0; 4]);
+    crc = !crc32::update(crc, &data[20..]);
+    if crc != header.crc32 {
+        return Err(error!(
+            efi::STATUS::CRC_ERROR,
+            "{} CRC mismatch ({:#x}, expected {:#x})", name, crc, header.crc32
+        ));
+    }
+
+    Ok(header.revision)
+}
+
+fn from_pointer<T>(ptr: *const T) -> &'static T {
+    unsafe { &*ptr }
 }
 
-impl From<Error> for Status {
-    fn from(error: Error) -> Self {
-        error.status
+fn _from_pointer_mut<T>(ptr: *mut T) -> &'static mut T {
+    unsafe { &mut *ptr }
+}
+
+// Printed as the specification writes them, e.g. 2.3.1 for 2.31. EFI 1.x
+// used two digit minor versions instead.
+impl fmt::Display for Revision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (major, minor) = (self.0 >> 16, self.0 & 0xFFFF);
+        if major < 2 {
+            return write!(f, "{}.{:02}", major, minor);
+        }
+
+        write!(f, "{}.{}", major, minor / 10)?;
+        if minor % 10 != 0 {
+            write!(f, ".{}", minor % 10)?;
+        }
+
+        Ok(())
     }
 }
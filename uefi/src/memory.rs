@@ -1,4 +1,5 @@
-use crate::efi;
+use crate::{config_table, efi};
+use alloc::{vec, vec::Vec};
 use core::{
     alloc::{GlobalAlloc, Layout},
     ffi::c_void,
@@ -16,6 +17,31 @@ pub struct MemoryMap {
     pub address: *const MemoryDescriptor,
 }
 
+// Larger tables than this are rejected rather than copied
+const MAXIMUM_ATTRIBUTES_TABLE_SIZE: usize = 0x100000;
+
+// The runtime services regions split into code and data, so the kernel can
+// map them with the right permissions. Copied out of firmware memory.
+pub struct MemoryAttributesTable {
+    pub version: u32,
+    pub flags: u32,
+    desc_size: usize,
+    count: usize,
+    // u64s so the descriptors are aligned
+    data: Vec<u64>,
+}
+
+// Passed to the kernel next to the memory map. The address is null if the
+// firmware has no memory attributes table.
+#[repr(C)]
+pub struct MemoryAttributes {
+    pub version: u32,
+    pub flags: u32,
+    pub count: usize,
+    pub desc_size: usize,
+    pub address: *const MemoryDescriptor,
+}
+
 struct UEFIAllocator {
     allocate: Option<efi::ALLOCATE_POOL>,
     free: Option<efi::FREE_POOL>,
@@ -24,7 +50,8 @@ struct UEFIAllocator {
     get_memory_map: Option<efi::GET_MEMORY_MAP>,
 }
 
-#[global_allocator]
+// Host tests use the standard allocator
+#[cfg_attr(not(test), global_allocator)]
 static mut ALLOCATOR: UEFIAllocator = UEFIAllocator {
     allocate: None,
     free: None,
@@ -66,10 +93,31 @@ pub fn allocate_pages(mem_size: usize, address: efi::PHYSICAL_ADDRESS) -> Result
                     (mem_size + 0xFFF) / 0x1000,
                     &mut address,
                 );
-                match status {
-                    efi::STATUS::SUCCESS => Ok(()),
-                    _ => Err(crate::Error::new(status, "Failed to allocate pages")),
-                }
+                status.to_result("Failed to allocate pages")
+            }
+        }
+    }
+}
+
+// Lets the firmware pick the address, for data that can live anywhere
+pub fn allocate_any_pages(mem_size: usize) -> Result<efi::PHYSICAL_ADDRESS, crate::Error> {
+    let mut address = 0;
+
+    unsafe {
+        match ALLOCATOR.allocate_pages {
+            None => Err(crate::Error::new(
+                efi::STATUS::NOT_READY,
+                "Allocator not setup",
+            )),
+            Some(allocate_pages) => {
+                let status = allocate_pages(
+                    efi::ALLOCATE_TYPE::AllocateAnyPages,
+                    efi::MEMORY_TYPE::LoaderData,
+                    (mem_size + 0xFFF) / 0x1000,
+                    &mut address,
+                );
+                status.to_result("Failed to allocate pages")?;
+                Ok(address)
             }
         }
     }
@@ -84,6 +132,21 @@ pub fn copy_mem(destination: *mut c_void, source: *const c_void, length: usize)
     }
 }
 
+pub(crate) fn free_pool(buffer: *const c_void) -> Result<(), crate::Error> {
+    unsafe {
+        match ALLOCATOR.free {
+            None => Err(crate::Error::new(
+                efi::STATUS::NOT_READY,
+                "Allocator not setup",
+            )),
+            Some(free_pool) => {
+                let status = free_pool(buffer);
+                status.to_result("Failed to free pool")
+            }
+        }
+    }
+}
+
 pub fn get_memory_map() -> Result<MemoryMap, crate::Error> {
     unsafe {
         match ALLOCATOR.get_memory_map {
@@ -106,7 +169,8 @@ pub fn get_memory_map() -> Result<MemoryMap, crate::Error> {
                         &mut desc_size,
                         &mut desc_version,
                     );
-                    if status == efi::STATUS::SUCCESS {
+                    if status != efi::STATUS::BUFFER_TOO_SMALL {
+                        status.to_result("Failed to get memory map")?;
                         return Ok(MemoryMap {
                             size: size,
                             key: key,
@@ -114,16 +178,12 @@ pub fn get_memory_map() -> Result<MemoryMap, crate::Error> {
                             desc_size: desc_size,
                             desc_version: desc_version,
                         });
-                    } else if status != efi::STATUS::BUFFER_TOO_SMALL {
-                        return Err(crate::Error::new(status, "Failed to get memory map"));
                     }
 
                     if addr != null_mut() {
                         let free_pool = ALLOCATOR.free.unwrap();
                         let status = free_pool(addr as *const _);
-                        if status != efi::STATUS::SUCCESS {
-                            return Err(crate::Error::new(status, "Failed to get memory map"));
-                        }
+                        status.to_result("Failed to get memory map")?;
                     }
 
                     let allocate_pool = ALLOCATOR.allocate.unwrap();
@@ -132,15 +192,14 @@ pub fn get_memory_map() -> Result<MemoryMap, crate::Error> {
                         size,
                         &mut addr as *mut *mut _ as *mut *const _,
                     );
-                    if status != efi::STATUS::SUCCESS {
-                        return Err(crate::Error::new(status, "Failed to get memory map"));
-                    }
+                    status.to_result("Failed to get memory map")?;
                 }
             }
         }
     }
 }
 
+#[cfg(not(test))]
 #[alloc_error_handler]
 fn alloc_error_handler(layout: alloc::alloc::Layout) -> ! {
     panic!("Allocation error: {:?}", layout)
@@ -152,13 +211,14 @@ unsafe impl GlobalAlloc for UEFIAllocator {
             None => null_mut(),
             Some(allocate) => {
                 let mut ret: *const efi::VOID = null_mut();
-                match allocate(
+                let status = allocate(
                     efi::MEMORY_TYPE::BootServicesData,
                     layout.size(),
                     &mut ret as *mut *const efi::VOID,
-                ) {
-                    efi::STATUS::SUCCESS => ret as *mut _,
-                    _ => null_mut(),
+                );
+                match status.to_result("Failed to allocate memory") {
+                    Ok(()) => ret as *mut _,
+                    Err(_) => null_mut(),
                 }
             }
         }
@@ -173,3 +233,233 @@ unsafe impl GlobalAlloc for UEFIAllocator {
         }
     }
 }
+
+impl MemoryMap {
+    pub fn descriptors(&self) -> impl Iterator<Item = &MemoryDescriptor> {
+        unsafe { descriptors(self.address, self.size / self.desc_size, self.desc_size) }
+    }
+
+    // Some firmware leaves the runtime attribute off regions the memory
+    // attributes table describes, which the kernel needs to map them. Only
+    // runtime services memory is flagged, other overlaps are left for
+    // `runtime_conflicts` to report.
+    pub fn flag_runtime_regions(&mut self, attributes: &MemoryAttributesTable) {
+        let address = self.address as *mut u8;
+        for i in 0..self.size / self.desc_size {
+            let descriptor =
+                unsafe { &mut *(address.add(i * self.desc_size) as *mut MemoryDescriptor) };
+            if is_runtime(descriptor)
+                && attributes
+                    .descriptors()
+                    .any(|entry| overlaps(descriptor, entry))
+            {
+                descriptor.attribute |= efi::MEMORY_RUNTIME;
+            }
+        }
+    }
+
+    // Descriptors that aren't runtime services memory but overlap the memory
+    // attributes table, meaning the firmware got one of the two wrong
+    pub fn runtime_conflicts<'a>(
+        &'a self,
+        attributes: &'a MemoryAttributesTable,
+    ) -> impl Iterator<Item = &'a MemoryDescriptor> {
+        self.descriptors().filter(move |descriptor| {
+            !is_runtime(descriptor)
+                && attributes
+                    .descriptors()
+                    .any(|entry| overlaps(descriptor, entry))
+        })
+    }
+
+    // Only for maps that aren't handed to the kernel
+    pub fn free(self) -> Result<(), crate::Error> {
+        free_pool(self.address as *const c_void)
+    }
+}
+
+impl MemoryAttributesTable {
+    // Iterates with the table's own descriptor size, which may differ from the memory map's
+    pub fn descriptors(&self) -> impl Iterator<Item = &MemoryDescriptor> {
+        unsafe {
+            descriptors(
+                self.data.as_ptr() as *const MemoryDescriptor,
+                self.count,
+                self.desc_size,
+            )
+        }
+    }
+
+    pub fn handoff(&self) -> MemoryAttributes {
+        MemoryAttributes {
+            version: self.version,
+            flags: self.flags,
+            count: self.count,
+            desc_size: self.desc_size,
+            address: self.data.as_ptr() as *const MemoryDescriptor,
+        }
+    }
+}
+
+impl MemoryAttributes {
+    pub fn empty() -> Self {
+        MemoryAttributes {
+            version: 0,
+            flags: 0,
+            count: 0,
+            desc_size: 0,
+            address: core::ptr::null(),
+        }
+    }
+}
+
+pub fn get_memory_attributes() -> Result<MemoryAttributesTable, crate::Error> {
+    crate::require_revision(
+        efi::SYSTEM_TABLE_REVISION_2_60,
+        "The memory attributes table",
+    )?;
+
+    let address = config_table::get_config_table(config_table::MEMORY_ATTRIBUTES_TABLE_GUID)?;
+    if address.is_null() {
+        return Err(crate::Error::new(
+            efi::STATUS::NOT_FOUND,
+            "Memory attributes table is null",
+        ));
+    }
+
+    let header = unsafe { &*(address as *const efi::MEMORY_ATTRIBUTES_TABLE) };
+    if header.version == 0 || header.version > efi::MEMORY_ATTRIBUTES_TABLE_VERSION {
+        return Err(crate::error!(
+            efi::STATUS::UNSUPPORTED,
+            "Unsupported memory attributes table version {}",
+            header.version
+        ));
+    }
+
+    let desc_size = header.descriptor_size as usize;
+    let count = header.number_of_entries as usize;
+    if desc_size < core::mem::size_of::<MemoryDescriptor>()
+        || desc_size % 8 != 0
+        || desc_size * count > MAXIMUM_ATTRIBUTES_TABLE_SIZE
+    {
+        return Err(crate::Error::new(
+            efi::STATUS::COMPROMISED_DATA,
+            "Invalid memory attributes table size",
+        ));
+    }
+
+    let mut data = vec![0u64; desc_size * count / 8];
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            (address as *const efi::MEMORY_ATTRIBUTES_TABLE).add(1) as *const u8,
+            data.as_mut_ptr() as *mut u8,
+            desc_size * count,
+        );
+    }
+
+    let table = MemoryAttributesTable {
+        version: header.version,
+        // Reserved in version 1
+        flags: if header.version >= 2 { header.flags } else { 0 },
+        desc_size,
+        count,
+        data,
+    };
+
+    // Entries must be page aligned runtime regions in ascending order
+    let mut end = 0;
+    for descriptor in table.descriptors() {
+        if !is_runtime(descriptor)
+            || descriptor.attribute & efi::MEMORY_RUNTIME == 0
+            || descriptor.physical_start % 0x1000 != 0
+            || descriptor.physical_start < end
+        {
+            return Err(crate::error!(
+                efi::STATUS::COMPROMISED_DATA,
+                "Invalid memory attributes table entry at {:#x}",
+                descriptor.physical_start
+            ));
+        }
+
+        end = descriptor.physical_start + descriptor.number_of_pages * 0x1000;
+    }
+
+    Ok(table)
+}
+
+unsafe fn descriptors<'a>(
+    address: *const MemoryDescriptor,
+    count: usize,
+    desc_size: usize,
+) -> impl Iterator<Item = &'a MemoryDescriptor> {
+    let address = address as *const u8;
+    (0..count).map(move |i| &*(address.add(i * desc_size) as *const MemoryDescriptor))
+}
+
+fn is_runtime(descriptor: &MemoryDescriptor) -> bool {
+    descriptor.memory_type == efi::MEMORY_TYPE::RuntimeServicesCode as u32
+        || descriptor.memory_type == efi::MEMORY_TYPE::RuntimeServicesData as u32
+}
+
+fn overlaps(a: &MemoryDescriptor, b: &MemoryDescriptor) -> bool {
+    a.physical_start < b.physical_start + b.number_of_pages * 0x1000
+        && b.physical_start < a.physical_start + a.number_of_pages * 0x1000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Firmware pads descriptors past the struct, so the tests do too
+    const DESC_SIZE: usize = 48;
+
+    fn descriptors(entries: &[(efi::MEMORY_TYPE, u64, u64)]) -> Vec<u64> {
+        entries
+            .iter()
+            .flat_map(|(memory_type, start, pages)| {
+                [*memory_type as u64, *start, 0, *pages, efi::MEMORY_WB, 0]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn flags_only_runtime_regions() {
+        let mut data = descriptors(&[
+            (efi::MEMORY_TYPE::ConventionalMemory, 0x0, 0x10),
+            (efi::MEMORY_TYPE::RuntimeServicesCode, 0x10000, 0x4),
+            (efi::MEMORY_TYPE::BootServicesData, 0x14000, 0x4),
+            (efi::MEMORY_TYPE::RuntimeServicesData, 0x18000, 0x4),
+        ]);
+        let mut map = MemoryMap {
+            size: data.len() * 8,
+            key: 0,
+            desc_size: DESC_SIZE,
+            desc_version: 1,
+            address: data.as_mut_ptr() as *const MemoryDescriptor,
+        };
+        let attributes = MemoryAttributesTable {
+            version: 2,
+            flags: 0,
+            desc_size: DESC_SIZE,
+            count: 2,
+            data: descriptors(&[
+                (efi::MEMORY_TYPE::RuntimeServicesCode, 0x10000, 0x4),
+                // Spills into the boot services region before it
+                (efi::MEMORY_TYPE::RuntimeServicesData, 0x17000, 0x5),
+            ]),
+        };
+
+        map.flag_runtime_regions(&attributes);
+        let runtime: Vec<bool> = map
+            .descriptors()
+            .map(|descriptor| descriptor.attribute & efi::MEMORY_RUNTIME != 0)
+            .collect();
+        assert_eq!(runtime, [false, true, false, true]);
+
+        let conflicts: Vec<u64> = map
+            .runtime_conflicts(&attributes)
+            .map(|descriptor| descriptor.physical_start)
+            .collect();
+        assert_eq!(conflicts, [0x14000]);
+    }
+}
@@ -0,0 +1,329 @@
+use crate::{
+    config_table::{get_config_table, GUID, SMBIOS3_TABLE_GUID, SMBIOS_TABLE_GUID},
+    efi,
+};
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{convert::TryInto, ffi::c_void, ptr::null};
+
+const ANCHOR_2: &[u8] = b"_SM_";
+const ANCHOR_3: &[u8] = b"_SM3_";
+const INTERMEDIATE_ANCHOR: &[u8] = b"_DMI_";
+
+const ENTRY_POINT_2_LENGTH: usize = 0x1F;
+const ENTRY_POINT_3_LENGTH: usize = 0x18;
+// Anything longer is assumed to be garbage rather than summed
+const ENTRY_POINT_MAXIMUM_LENGTH: usize = 0x40;
+
+const TYPE_BIOS: u8 = 0;
+const TYPE_SYSTEM: u8 = 1;
+const TYPE_MEMORY_DEVICE: u8 = 17;
+const TYPE_END: u8 = 127;
+
+// Passed to the kernel. The address is null if the firmware has no SMBIOS
+// tables, and the table length is a maximum rather than exact for 3.x.
+#[repr(C)]
+pub struct EntryPoint {
+    pub address: *const c_void,
+    pub major_version: u8,
+    pub minor_version: u8,
+    pub table_address: u64,
+    pub table_length: u32,
+    // False if a checksum failed, which the kernel may choose to ignore
+    pub valid: bool,
+}
+
+// One structure from the table, with its formatted area and strings
+pub struct Structure<'a> {
+    pub structure_type: u8,
+    pub handle: u16,
+    pub data: &'a [u8],
+    strings: &'a [u8],
+}
+
+pub struct Structures<'a> {
+    table: &'a [u8],
+    offset: usize,
+}
+
+pub struct BiosInfo {
+    pub vendor: String,
+    pub version: String,
+    pub release_date: String,
+}
+
+pub struct SystemInfo {
+    pub manufacturer: String,
+    pub product_name: String,
+    pub version: String,
+    pub serial_number: String,
+    // None if the firmware doesn't set one
+    pub uuid: Option<GUID>,
+}
+
+pub struct MemoryDevice {
+    pub locator: String,
+    pub bank_locator: String,
+    pub manufacturer: String,
+    pub part_number: String,
+    // In bytes, 0 if the slot is empty and None if unknown
+    pub size: Option<u64>,
+    // In MT/s, 0 if unknown
+    pub speed: u32,
+    pub memory_type: u8,
+}
+
+// The parts of the structure table the bootloader reports
+pub struct Smbios {
+    pub bios: Option<BiosInfo>,
+    pub system: Option<SystemInfo>,
+    pub memory_devices: Vec<MemoryDevice>,
+}
+
+impl EntryPoint {
+    pub fn empty() -> Self {
+        EntryPoint {
+            address: null(),
+            major_version: 0,
+            minor_version: 0,
+            table_address: 0,
+            table_length: 0,
+            valid: false,
+        }
+    }
+
+    pub fn structures(&self) -> Structures<'_> {
+        let table = if self.table_address == 0 {
+            &[]
+        } else {
+            unsafe {
+                core::slice::from_raw_parts(
+                    self.table_address as usize as *const u8,
+                    self.table_length as usize,
+                )
+            }
+        };
+
+        Structures { table, offset: 0 }
+    }
+}
+
+// Prefers the 3.x entry point, which can point above 4 GiB
+pub fn get_entry_point() -> Result<EntryPoint, crate::Error> {
+    let mut result = Err(crate::Error::new(
+        efi::STATUS::NOT_FOUND,
+        "Failed to find the SMBIOS entry point",
+    ));
+
+    for guid in [SMBIOS3_TABLE_GUID, SMBIOS_TABLE_GUID] {
+        let address = match get_config_table(guid) {
+            Ok(address) if !address.is_null() => address,
+            _ => continue,
+        };
+
+        result = unsafe { parse_entry_point(address) };
+        if result.is_ok() {
+            break;
+        }
+    }
+
+    result
+}
+
+impl Smbios {
+    pub fn read(entry_point: &EntryPoint) -> Self {
+        let mut smbios = Smbios {
+            bios: None,
+            system: None,
+            memory_devices: Vec::new(),
+        };
+
+        for structure in entry_point.structures() {
+            match structure.structure_type {
+                TYPE_BIOS if structure.data.len() >= 0x09 => {
+                    smbios.bios = Some(BiosInfo {
+                        vendor: structure.string_at(0x04),
+                        version: structure.string_at(0x05),
+                        release_date: structure.string_at(0x08),
+                    })
+                }
+                TYPE_SYSTEM if structure.data.len() >= 0x08 => {
+                    smbios.system = Some(SystemInfo {
+                        manufacturer: structure.string_at(0x04),
+                        product_name: structure.string_at(0x05),
+                        version: structure.string_at(0x06),
+                        serial_number: structure.string_at(0x07),
+                        uuid: structure.data.get(0x08..0x18).and_then(parse_uuid),
+                    })
+                }
+                TYPE_MEMORY_DEVICE if structure.data.len() >= 0x15 => {
+                    smbios.memory_devices.push(parse_memory_device(&structure))
+                }
+                _ => {}
+            }
+        }
+
+        smbios
+    }
+}
+
+impl<'a> Structure<'a> {
+    // String numbers start at one, and zero means there is no string
+    pub fn string(&self, number: u8) -> Option<&'a [u8]> {
+        if number == 0 {
+            return None;
+        }
+
+        self.strings
+            .split(|byte| *byte == 0)
+            .take_while(|string| !string.is_empty())
+            .nth(number as usize - 1)
+    }
+
+    fn string_at(&self, offset: usize) -> String {
+        match self
+            .data
+            .get(offset)
+            .and_then(|number| self.string(*number))
+        {
+            Some(string) => String::from_utf8_lossy(string).trim().to_string(),
+            None => String::new(),
+        }
+    }
+}
+
+impl<'a> Iterator for Structures<'a> {
+    type Item = Structure<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let remaining = self.table.get(self.offset..)?;
+        if remaining.len() < 4 {
+            return None;
+        }
+
+        let length = remaining[1] as usize;
+        if length < 4 || length > remaining.len() {
+            return None;
+        }
+
+        // The strings end with a double nul, which is also there when there are none
+        let strings = &remaining[length..];
+        let strings_length = strings.windows(2).position(|pair| pair == [0, 0])? + 2;
+
+        let structure_type = remaining[0];
+        if structure_type == TYPE_END {
+            self.offset = self.table.len();
+        } else {
+            self.offset += length + strings_length;
+        }
+
+        Some(Structure {
+            structure_type,
+            handle: u16::from_le_bytes([remaining[2], remaining[3]]),
+            data: &remaining[..length],
+            strings: &strings[..strings_length],
+        })
+    }
+}
+
+// A bad anchor means this isn't an entry point at all, but bad checksums
+// only mark it as invalid
+unsafe fn parse_entry_point(address: *const c_void) -> Result<EntryPoint, crate::Error> {
+    let header = core::slice::from_raw_parts(address as *const u8, ENTRY_POINT_3_LENGTH);
+
+    if header.starts_with(ANCHOR_3) {
+        let length = header[0x06] as usize;
+        let valid = (ENTRY_POINT_3_LENGTH..=ENTRY_POINT_MAXIMUM_LENGTH).contains(&length)
+            && checksum(core::slice::from_raw_parts(address as *const u8, length));
+
+        return Ok(EntryPoint {
+            address,
+            major_version: header[0x07],
+            minor_version: header[0x08],
+            table_address: u64::from_le_bytes(header[0x10..0x18].try_into().unwrap()),
+            table_length: u32::from_le_bytes(header[0x0C..0x10].try_into().unwrap()),
+            valid,
+        });
+    }
+
+    if header.starts_with(ANCHOR_2) {
+        let header = core::slice::from_raw_parts(address as *const u8, ENTRY_POINT_2_LENGTH);
+        let length = header[0x05] as usize;
+
+        // The intermediate checksum covers the legacy DMI part from the second anchor
+        let valid = (ENTRY_POINT_2_LENGTH..=ENTRY_POINT_MAXIMUM_LENGTH).contains(&length)
+            && checksum(core::slice::from_raw_parts(address as *const u8, length))
+            && &header[0x10..0x15] == INTERMEDIATE_ANCHOR
+            && checksum(&header[0x10..0x1F]);
+
+        return Ok(EntryPoint {
+            address,
+            major_version: header[0x06],
+            minor_version: header[0x07],
+            table_address: u32::from_le_bytes(header[0x18..0x1C].try_into().unwrap()) as u64,
+            table_length: u16::from_le_bytes([header[0x16], header[0x17]]) as u32,
+            valid,
+        });
+    }
+
+    Err(crate::Error::new(
+        efi::STATUS::COMPROMISED_DATA,
+        "Invalid SMBIOS entry point anchor",
+    ))
+}
+
+// All zeroes means there is no UUID and all ones means it isn't set. The first
+// three fields are little endian like an EFI GUID, as of SMBIOS 2.6.
+fn parse_uuid(data: &[u8]) -> Option<GUID> {
+    if data.iter().all(|byte| *byte == 0) || data.iter().all(|byte| *byte == 0xFF) {
+        return None;
+    }
+
+    Some(GUID {
+        a: u32::from_le_bytes(data[0..4].try_into().unwrap()),
+        b: u16::from_le_bytes([data[4], data[5]]),
+        c: u16::from_le_bytes([data[6], data[7]]),
+        d: data[8..16].try_into().unwrap(),
+    })
+}
+
+fn parse_memory_device(structure: &Structure) -> MemoryDevice {
+    let data = structure.data;
+    let read_u16 = |offset: usize| {
+        data.get(offset..offset + 2)
+            .map_or(0, |bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+    };
+    let read_u32 = |offset: usize| {
+        data.get(offset..offset + 4)
+            .map_or(0, |bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+    };
+
+    // Sizes are in MiB, or KiB with the top bit set, with large ones in the extended field
+    let size = match read_u16(0x0C) {
+        0xFFFF => None,
+        0x7FFF => Some((read_u32(0x1C) & 0x7FFFFFFF) as u64 * 1024 * 1024),
+        size if size & 0x8000 != 0 => Some((size & 0x7FFF) as u64 * 1024),
+        size => Some(size as u64 * 1024 * 1024),
+    };
+
+    let speed = match read_u16(0x15) {
+        0xFFFF => read_u32(0x54),
+        speed => speed as u32,
+    };
+
+    MemoryDevice {
+        locator: structure.string_at(0x10),
+        bank_locator: structure.string_at(0x11),
+        manufacturer: structure.string_at(0x17),
+        part_number: structure.string_at(0x1A),
+        size,
+        speed,
+        memory_type: data[0x12],
+    }
+}
+
+fn checksum(data: &[u8]) -> bool {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0
+}
@@ -0,0 +1,410 @@
+use crate::efi;
+use alloc::{string::String, vec::Vec};
+use core::{convert::TryFrom, fmt, ops::Deref, str::FromStr};
+
+pub const REPLACEMENT_CHARACTER: efi::CHAR16 = b'?' as efi::CHAR16;
+
+const HIGH_SURROGATE_START: u32 = 0xD800;
+const LOW_SURROGATE_START: u32 = 0xDC00;
+const SUPPLEMENTARY_START: u32 = 0x10000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ucs2Policy {
+    Replace,
+    Skip,
+    Error,
+}
+
+#[repr(transparent)]
+pub struct CStr16([efi::CHAR16]);
+
+#[derive(Clone)]
+pub struct CString16(Vec<efi::CHAR16>);
+
+impl CStr16 {
+    pub fn from_u16_with_nul(codes: &[efi::CHAR16]) -> Result<&CStr16, crate::Error> {
+        match codes.iter().position(|c| *c == 0) {
+            Some(nul) if nul == codes.len() - 1 => {
+                Ok(unsafe { CStr16::from_u16_with_nul_unchecked(codes) })
+            }
+            Some(_) => Err(crate::Error::new(
+                efi::STATUS::INVALID_PARAMETER,
+                "String contains an interior null",
+            )),
+            None => Err(crate::Error::new(
+                efi::STATUS::INVALID_PARAMETER,
+                "String is not null terminated",
+            )),
+        }
+    }
+
+    /// # Safety
+    /// `codes` must end with the only null in the slice
+    pub const unsafe fn from_u16_with_nul_unchecked(codes: &[efi::CHAR16]) -> &CStr16 {
+        &*(codes as *const [efi::CHAR16] as *const CStr16)
+    }
+
+    /// # Safety
+    /// `ptr` must point to a null terminated string that lives for `'a`
+    pub unsafe fn from_ptr<'a>(ptr: *const efi::CHAR16) -> &'a CStr16 {
+        let mut len = 0;
+        while *ptr.add(len) != 0 {
+            len += 1;
+        }
+
+        CStr16::from_u16_with_nul_unchecked(core::slice::from_raw_parts(ptr, len + 1))
+    }
+
+    pub fn as_ptr(&self) -> *const efi::CHAR16 {
+        self.0.as_ptr()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn as_slice(&self) -> &[efi::CHAR16] {
+        &self.0[..self.len()]
+    }
+
+    pub fn as_slice_with_nul(&self) -> &[efi::CHAR16] {
+        &self.0
+    }
+
+    pub fn chars(&self) -> impl Iterator<Item = Result<char, crate::Error>> + '_ {
+        core::char::decode_utf16(self.as_slice().iter().cloned()).map(|c| {
+            c.map_err(|_| {
+                crate::Error::new(
+                    efi::STATUS::COMPROMISED_DATA,
+                    "Unpaired surrogate in string",
+                )
+            })
+        })
+    }
+
+    pub fn try_to_string(&self) -> Result<String, crate::Error> {
+        self.chars().collect()
+    }
+
+    pub fn to_string_lossy(&self) -> String {
+        core::char::decode_utf16(self.as_slice().iter().cloned())
+            .map(|c| c.unwrap_or(core::char::REPLACEMENT_CHARACTER))
+            .collect()
+    }
+}
+
+impl fmt::Display for CStr16 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in core::char::decode_utf16(self.as_slice().iter().cloned()) {
+            fmt::Write::write_char(f, c.unwrap_or(core::char::REPLACEMENT_CHARACTER))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl PartialEq for CStr16 {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl CString16 {
+    pub fn new() -> Self {
+        CString16(alloc::vec![0])
+    }
+
+    // Characters outside the basic multilingual plane are handled by `policy`
+    pub fn from_str_ucs2(s: &str, policy: Ucs2Policy) -> Result<Self, crate::Error> {
+        let mut codes = Vec::with_capacity(s.len() + 1);
+        for c in s.chars() {
+            match encode_ucs2(c, policy)? {
+                Some(0) => return Err(interior_null()),
+                Some(code) => codes.push(code),
+                None => {}
+            }
+        }
+        codes.push(0);
+
+        Ok(CString16(codes))
+    }
+
+    // Leaves the string unchanged on error
+    pub fn push_str(&mut self, s: &str) -> Result<(), crate::Error> {
+        if s.contains('\0') {
+            return Err(interior_null());
+        }
+
+        self.0.pop();
+        self.0.extend(s.encode_utf16());
+        self.0.push(0);
+
+        Ok(())
+    }
+}
+
+impl Default for CString16 {
+    fn default() -> Self {
+        CString16::new()
+    }
+}
+
+impl Deref for CString16 {
+    type Target = CStr16;
+
+    fn deref(&self) -> &CStr16 {
+        unsafe { CStr16::from_u16_with_nul_unchecked(&self.0) }
+    }
+}
+
+// Characters outside the basic multilingual plane become surrogate pairs
+impl FromStr for CString16 {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut codes = Vec::with_capacity(s.len() + 1);
+        for code in s.encode_utf16() {
+            if code == 0 {
+                return Err(interior_null());
+            }
+
+            codes.push(code);
+        }
+        codes.push(0);
+
+        Ok(CString16(codes))
+    }
+}
+
+impl TryFrom<&str> for CString16 {
+    type Error = crate::Error;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for CString16 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+pub fn encode_ucs2(c: char, policy: Ucs2Policy) -> Result<Option<efi::CHAR16>, crate::Error> {
+    if (c as u32) < SUPPLEMENTARY_START {
+        return Ok(Some(c as efi::CHAR16));
+    }
+
+    match policy {
+        Ucs2Policy::Replace => Ok(Some(REPLACEMENT_CHARACTER)),
+        Ucs2Policy::Skip => Ok(None),
+        Ucs2Policy::Error => Err(crate::Error::new(
+            efi::STATUS::UNSUPPORTED,
+            "Character cannot be represented in UCS-2",
+        )),
+    }
+}
+
+#[doc(hidden)]
+pub const fn utf16_len(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let mut len = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let (c, width) = decode_utf8(bytes, i);
+        len += if c >= SUPPLEMENTARY_START { 2 } else { 1 };
+        i += width;
+    }
+
+    len
+}
+
+#[doc(hidden)]
+pub const fn encode_utf16_with_nul<const N: usize>(s: &str) -> [efi::CHAR16; N] {
+    let bytes = s.as_bytes();
+    let mut buffer = [0; N];
+    let mut i = 0;
+    let mut j = 0;
+    while i < bytes.len() {
+        let (c, width) = decode_utf8(bytes, i);
+        if c == 0 {
+            panic!("String literal contains an interior null");
+        }
+
+        if c >= SUPPLEMENTARY_START {
+            let c = c - SUPPLEMENTARY_START;
+            buffer[j] = (HIGH_SURROGATE_START + (c >> 10)) as efi::CHAR16;
+            buffer[j + 1] = (LOW_SURROGATE_START + (c & 0x3FF)) as efi::CHAR16;
+            j += 2;
+        } else {
+            buffer[j] = c as efi::CHAR16;
+            j += 1;
+        }
+
+        i += width;
+    }
+
+    if j + 1 != N {
+        panic!("Buffer size does not match the string length");
+    }
+
+    buffer
+}
+
+// Decodes the UTF-8 sequence at `i`, which is valid as it came from a `str`
+const fn decode_utf8(bytes: &[u8], i: usize) -> (u32, usize) {
+    let b0 = bytes[i] as u32;
+    if b0 < 0x80 {
+        (b0, 1)
+    } else if b0 < 0xE0 {
+        (((b0 & 0x1F) << 6) | (bytes[i + 1] as u32 & 0x3F), 2)
+    } else if b0 < 0xF0 {
+        (
+            ((b0 & 0x0F) << 12)
+                | ((bytes[i + 1] as u32 & 0x3F) << 6)
+                | (bytes[i + 2] as u32 & 0x3F),
+            3,
+        )
+    } else {
+        (
+            ((b0 & 0x07) << 18)
+                | ((bytes[i + 1] as u32 & 0x3F) << 12)
+                | ((bytes[i + 2] as u32 & 0x3F) << 6)
+                | (bytes[i + 3] as u32 & 0x3F),
+            4,
+        )
+    }
+}
+
+fn interior_null() -> crate::Error {
+    crate::Error::new(
+        efi::STATUS::INVALID_PARAMETER,
+        "String contains an interior null",
+    )
+}
+
+/// Creates a `&'static CStr16` from a string literal at compile time
+#[macro_export]
+macro_rules! u16 {
+    ($s:literal) => {{
+        const S: &str = $s;
+        const LEN: usize = $crate::string::utf16_len(S) + 1;
+        const BUFFER: [u16; LEN] = $crate::string::encode_utf16_with_nul::<LEN>(S);
+        unsafe { $crate::string::CStr16::from_u16_with_nul_unchecked(&BUFFER) }
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // U+1F600 as a surrogate pair
+    const GRINNING_FACE: [efi::CHAR16; 2] = [0xD83D, 0xDE00];
+
+    #[test]
+    fn encodes_surrogate_pairs() {
+        let s: CString16 = "a\u{1F600}b".parse().unwrap();
+        assert_eq!(
+            s.as_slice_with_nul(),
+            &[0x61, GRINNING_FACE[0], GRINNING_FACE[1], 0x62, 0]
+        );
+        assert_eq!(s.len(), 4);
+    }
+
+    #[test]
+    fn decodes_surrogate_pairs() {
+        let codes = [0x61, GRINNING_FACE[0], GRINNING_FACE[1], 0];
+        let s = CStr16::from_u16_with_nul(&codes).unwrap();
+        assert_eq!(s.try_to_string().unwrap(), "a\u{1F600}");
+        assert_eq!(s.to_string_lossy(), "a\u{1F600}");
+        assert_eq!(alloc::format!("{}", s), "a\u{1F600}");
+    }
+
+    #[test]
+    fn handles_unpaired_surrogates() {
+        for codes in &[
+            [0x61, GRINNING_FACE[0], 0x62, 0],
+            [0x61, GRINNING_FACE[1], 0x62, 0],
+            [0x61, GRINNING_FACE[1], GRINNING_FACE[0], 0],
+        ] {
+            let s = CStr16::from_u16_with_nul(codes).unwrap();
+            let err = s.try_to_string().err().unwrap();
+            assert_eq!(err.status(), efi::STATUS::COMPROMISED_DATA);
+            assert!(s
+                .to_string_lossy()
+                .contains(core::char::REPLACEMENT_CHARACTER));
+            assert!(alloc::format!("{}", s).starts_with('a'));
+        }
+    }
+
+    #[test]
+    fn applies_ucs2_policy() {
+        let replaced = CString16::from_str_ucs2("a\u{1F600}b", Ucs2Policy::Replace).unwrap();
+        assert_eq!(
+            replaced.as_slice_with_nul(),
+            &[0x61, REPLACEMENT_CHARACTER, 0x62, 0]
+        );
+
+        let skipped = CString16::from_str_ucs2("a\u{1F600}b", Ucs2Policy::Skip).unwrap();
+        assert_eq!(skipped.as_slice_with_nul(), &[0x61, 0x62, 0]);
+
+        let err = CString16::from_str_ucs2("a\u{1F600}b", Ucs2Policy::Error)
+            .err()
+            .unwrap();
+        assert_eq!(err.status(), efi::STATUS::UNSUPPORTED);
+
+        // Everything in the basic multilingual plane passes under every policy
+        for policy in &[Ucs2Policy::Replace, Ucs2Policy::Skip, Ucs2Policy::Error] {
+            assert_eq!(encode_ucs2('\u{FFFD}', *policy).unwrap(), Some(0xFFFD));
+            assert!(CString16::from_str_ucs2("a\0b", *policy).is_err());
+        }
+    }
+
+    #[test]
+    fn checks_null_terminator() {
+        let err = CStr16::from_u16_with_nul(&[0x61, 0x62]).err().unwrap();
+        assert_eq!(err.status(), efi::STATUS::INVALID_PARAMETER);
+        assert_eq!(err.message(), "String is not null terminated");
+
+        let err = CStr16::from_u16_with_nul(&[0x61, 0, 0x62, 0])
+            .err()
+            .unwrap();
+        assert_eq!(err.status(), efi::STATUS::INVALID_PARAMETER);
+        assert_eq!(err.message(), "String contains an interior null");
+
+        assert!(CStr16::from_u16_with_nul(&[]).is_err());
+        assert!(CStr16::from_u16_with_nul(&[0]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_interior_nulls() {
+        assert!("a\0b".parse::<CString16>().is_err());
+
+        let mut s = CString16::new();
+        s.push_str("ab").unwrap();
+        assert!(s.push_str("c\0d").is_err());
+        assert_eq!(s.as_slice_with_nul(), &[0x61, 0x62, 0]);
+
+        s.push_str("c").unwrap();
+        assert_eq!(s.as_slice_with_nul(), &[0x61, 0x62, 0x63, 0]);
+    }
+
+    #[test]
+    fn builds_literals() {
+        let s = crate::u16!("EFI\\BOOT");
+        assert_eq!(s.as_slice(), "EFI\\BOOT".encode_utf16().collect::<Vec<_>>());
+        assert_eq!(s.as_slice_with_nul().last(), Some(&0));
+
+        let s = crate::u16!("é\u{1F600}");
+        assert_eq!(
+            s.as_slice_with_nul(),
+            &[0xE9, GRINNING_FACE[0], GRINNING_FACE[1], 0]
+        );
+
+        assert!(crate::u16!("").is_empty());
+    }
+}
@@ -0,0 +1,28 @@
+#!/bin/sh
+# Regenerates ext2.img, the image the ext2 unit tests read
+set -e
+cd "$(dirname "$0")"
+root=$(mktemp -d)
+trap 'rm -rf "$root"' EXIT
+
+mkdir -p "$root/dir/nested"
+printf 'Hello, world!\n' > "$root/hello.txt"
+printf 'nested file\n' > "$root/dir/nested/file.txt"
+
+# 20 KiB needs the single indirect block with 1 KiB blocks
+python3 -c 'import sys; sys.stdout.buffer.write(bytes(i * 7 % 251 for i in range(20480)))' > "$root/single.bin"
+
+# Data at the start and past block 268, which needs the double indirect block,
+# with a hole in between
+python3 -c 'import sys; sys.stdout.buffer.write(bytes(i * 13 % 251 + 1 for i in range(4096)))' > "$root/sparse.bin"
+python3 -c 'import sys; sys.stdout.buffer.write(bytes(i * 13 % 251 + 1 for i in range(4096)))' |
+    dd of="$root/sparse.bin" bs=1024 seek=300 conv=notrunc status=none
+
+ln -s dir/nested/file.txt "$root/fast-link"
+ln -s ./dir/../dir/nested/../nested/../nested/../nested/../nested/file.txt "$root/slow-link"
+ln -s /hello.txt "$root/absolute-link"
+
+rm -f ext2.img
+E2FSPROGS_FAKE_TIME=1700000000 mke2fs -q -t ext2 -b 1024 -N 32 -L testfs \
+    -U 01234567-89ab-cdef-0123-456789abcdef -E root_owner=0:0,hash_seed=01234567-89ab-cdef-0123-456789abcdef \
+    -d "$root" ext2.img 128k